@@ -0,0 +1,159 @@
+use crate::TranscriptItem;
+
+// Find the entries whose text contains `query`
+pub fn search<'a>(
+    transcript: &'a [TranscriptItem],
+    query: &str,
+    case_insensitive: bool,
+) -> Vec<&'a TranscriptItem> {
+    if case_insensitive {
+        let query = query.to_lowercase();
+        transcript
+            .iter()
+            .filter(|item| item.text.to_lowercase().contains(&query))
+            .collect()
+    } else {
+        transcript
+            .iter()
+            .filter(|item| item.text.contains(query))
+            .collect()
+    }
+}
+
+// Summary figures for a transcript
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptStats {
+    pub word_count: usize,
+    // Seconds from the start of the video to the end of the last entry
+    pub total_duration: f64,
+    pub words_per_minute: f64,
+}
+
+pub fn transcript_stats(transcript: &[TranscriptItem]) -> TranscriptStats {
+    let word_count = transcript
+        .iter()
+        .map(|item| item.text.split_whitespace().count())
+        .sum();
+    let total_duration = transcript
+        .iter()
+        .map(|item| item.end())
+        .fold(0.0, f64::max);
+    let words_per_minute = if total_duration > 0.0 {
+        word_count as f64 / (total_duration / 60.0)
+    } else {
+        0.0
+    };
+
+    TranscriptStats {
+        word_count,
+        total_duration,
+        words_per_minute,
+    }
+}
+
+// Stretches where nobody speaks: (gap_start, gap_end) pairs for every place
+// where more than `min_gap_secs` pass between the end of one entry and the
+// start of the next. Entries that overlap count as continuous speech
+pub fn find_gaps(transcript: &[TranscriptItem], min_gap_secs: f64) -> Vec<(f64, f64)> {
+    let mut items: Vec<&TranscriptItem> = transcript.iter().collect();
+    items.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut gaps = Vec::new();
+    let mut spoken_until: Option<f64> = None;
+    for item in items {
+        if let Some(end) = spoken_until {
+            if item.start - end > min_gap_secs {
+                gaps.push((end, item.start));
+            }
+        }
+        spoken_until = Some(spoken_until.map_or(item.end(), |end| end.max(item.end())));
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(text: &str, start: f64, duration: f64) -> TranscriptItem {
+        TranscriptItem {
+            text: text.to_string(),
+            start,
+            duration,
+            index: 0,
+        }
+    }
+
+    fn texts(found: Vec<&TranscriptItem>) -> Vec<&str> {
+        found.iter().map(|item| item.text.as_str()).collect()
+    }
+
+    #[test]
+    fn search_matches_case_sensitively_or_not() {
+        let transcript = [
+            item("Rust is fast", 0.0, 2.0),
+            item("I like rust", 2.0, 2.0),
+            item("Nothing here", 4.0, 2.0),
+        ];
+        assert_eq!(texts(search(&transcript, "rust", false)), ["I like rust"]);
+        assert_eq!(
+            texts(search(&transcript, "RUST", true)),
+            ["Rust is fast", "I like rust"]
+        );
+    }
+
+    #[test]
+    fn search_without_a_match_is_empty() {
+        let transcript = [item("Rust is fast", 0.0, 2.0)];
+        assert!(search(&transcript, "python", true).is_empty());
+        assert!(search(&[], "rust", false).is_empty());
+    }
+
+    #[test]
+    fn stats_count_words_and_time_to_the_latest_end() {
+        // The second entry runs past the start and end of the third
+        let transcript = [
+            item("one two three", 0.0, 10.0),
+            item("four  five", 10.0, 110.0),
+            item("six", 40.0, 5.0),
+        ];
+        assert_eq!(
+            transcript_stats(&transcript),
+            TranscriptStats {
+                word_count: 6,
+                total_duration: 120.0,
+                words_per_minute: 3.0,
+            }
+        );
+    }
+
+    #[test]
+    fn stats_of_nothing_are_zero() {
+        let stats = transcript_stats(&[]);
+        assert_eq!(stats.word_count, 0);
+        assert_eq!(stats.words_per_minute, 0.0);
+    }
+
+    #[test]
+    fn finds_the_one_clear_gap() {
+        let transcript = [
+            item("a", 0.0, 2.0),
+            item("b", 2.5, 2.0),
+            item("c", 12.0, 1.0),
+            item("d", 13.5, 1.0),
+        ];
+        assert_eq!(find_gaps(&transcript, 3.0), [(4.5, 12.0)]);
+        // The threshold itself doesn't count as a gap
+        assert!(find_gaps(&transcript, 7.5).is_empty());
+    }
+
+    #[test]
+    fn overlapping_and_unsorted_entries_count_as_speech() {
+        let transcript = [
+            item("late", 30.0, 1.0),
+            item("long", 0.0, 20.0),
+            item("inside", 5.0, 1.0),
+        ];
+        assert_eq!(find_gaps(&transcript, 1.0), [(20.0, 30.0)]);
+    }
+}
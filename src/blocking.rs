@@ -0,0 +1,153 @@
+// Synchronous versions of the fetch functions, for scripts and other code that
+// doesn't run an async runtime. Enabled with the "blocking" feature
+use crate::http::{body_snippet, redirect_policy, MAX_ERROR_BODY_LEN};
+use crate::{
+    build_youtube_url_with, caption_base_url, check_watch_url, is_consent_page, is_consent_url,
+    json3_url, load_cookie_jar, parse_caption_response, parse_player_response, validate_video_id,
+    FetchOptions, TranscriptError, TranscriptItem, CONSENT_COOKIES,
+};
+use log::info;
+use reqwest::blocking::{Client, Response};
+use reqwest::Proxy;
+use std::io::Read;
+use std::sync::Arc;
+
+// The blocking counterpart of the async client's status check
+fn check_status(response: Response) -> Result<Response, TranscriptError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let mut body = Vec::new();
+    // A read error just leaves the snippet shorter
+    let _ = response.take(MAX_ERROR_BODY_LEN as u64).read_to_end(&mut body);
+    Err(TranscriptError::BadStatus {
+        code: status.as_u16(),
+        snippet: body_snippet(&String::from_utf8_lossy(&body)),
+    })
+}
+
+// The blocking counterpart of crate::http::read_text
+fn read_text(response: Response, limit: usize) -> Result<String, TranscriptError> {
+    if response.content_length().is_some_and(|len| len > limit as u64) {
+        return Err(TranscriptError::ResponseTooLarge { limit });
+    }
+    // One byte past the limit is enough to know it was exceeded
+    let mut body = Vec::new();
+    response.take(limit as u64 + 1).read_to_end(&mut body)?;
+    if body.len() > limit {
+        return Err(TranscriptError::ResponseTooLarge { limit });
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+fn build_client(options: &FetchOptions) -> Result<Client, TranscriptError> {
+    let mut builder = Client::builder()
+        .user_agent(options.user_agent())
+        .timeout(options.timeout)
+        .redirect(redirect_policy())
+        .gzip(true)
+        .brotli(true)
+        .deflate(true);
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    if let Some(path) = &options.cookies {
+        builder = builder.cookie_provider(Arc::new(load_cookie_jar(path)?));
+    }
+    Ok(builder.build()?)
+}
+
+// Blocking counterpart of crate::fetch_transcript
+pub fn fetch_transcript_blocking(video_id: &str) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    fetch_transcript_blocking_with(video_id, None, &FetchOptions::default())
+}
+
+// Blocking counterpart of crate::fetch_transcript_with_lang, with explicit options
+// Requests aren't retried here and nothing is cached: options.retries, use_api
+// and cache_dir only apply to the async client
+pub fn fetch_transcript_blocking_with(
+    video_id: &str,
+    lang: Option<&str>,
+    options: &FetchOptions,
+) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    validate_video_id(video_id)?;
+    let client = build_client(options)?;
+    let url = build_youtube_url_with(options.base_url(), video_id);
+
+    info!("Fetching video page...");
+    let response = check_status(client.get(&url).send()?)?;
+    let consent = is_consent_url(response.url());
+    if !consent {
+        check_watch_url(response.url(), options.base_url())?;
+    }
+    let mut html = read_text(response, options.max_page_size)?;
+    if consent || is_consent_page(&html) {
+        info!("Got the consent page, retrying with consent cookies...");
+        let response = check_status(
+            client
+                .get(&url)
+                .header(reqwest::header::COOKIE, CONSENT_COOKIES)
+                .send()?,
+        )?;
+        check_watch_url(response.url(), options.base_url())?;
+        html = read_text(response, options.max_page_size)?;
+    }
+
+    let parsed = parse_player_response(&html)?;
+    let base_url = caption_base_url(&parsed, lang, options.translate.as_deref())?;
+
+    info!("Downloading transcript...");
+    let response = check_status(client.get(json3_url(&base_url)).send()?)?;
+    let body = read_text(response, options.max_caption_size)?;
+    let mut transcript = parse_caption_response(&body, options.min_confidence)?;
+    transcript.truncate(options.limit.unwrap_or(usize::MAX));
+    Ok(transcript)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{json3_body, mock_video, MockResponse, MockServer};
+    use std::fs;
+
+    #[test]
+    fn fetches_synchronously_and_sends_the_cookies() {
+        let server = MockServer::start();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], &json3_body(&[(0, 1500, "hello")]));
+        let dir = tempfile::tempdir().unwrap();
+        let cookies = dir.path().join("cookies.txt");
+        fs::write(&cookies, "127.0.0.1\tFALSE\t/\tFALSE\t0\tSID\tsid-value\n").unwrap();
+
+        let options = FetchOptions {
+            base_url: server.url().to_string(),
+            cookies: Some(cookies),
+            ..FetchOptions::default()
+        };
+        let transcript = fetch_transcript_blocking_with("dQw4w9WgXcQ", None, &options).unwrap();
+        assert_eq!(transcript.len(), 1);
+        assert_eq!(transcript[0].text, "hello");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| r.header("cookie") == Some("SID=sid-value")));
+    }
+
+    #[test]
+    fn only_the_start_of_a_huge_error_body_is_read() {
+        let server = MockServer::start();
+        let body = "x".repeat(16 * 1024 * 1024);
+        server.mock("/watch", MockResponse::status(404, body).chunked());
+
+        let options = FetchOptions {
+            base_url: server.url().to_string(),
+            ..FetchOptions::default()
+        };
+        let error = fetch_transcript_blocking_with("dQw4w9WgXcQ", None, &options).unwrap_err();
+        let TranscriptError::BadStatus { code, snippet } = error else {
+            panic!("expected BadStatus, got {:?}", error);
+        };
+        assert_eq!(code, 404);
+        assert_eq!(snippet, format!("{}...", "x".repeat(200)));
+    }
+}
@@ -0,0 +1,52 @@
+use crate::{write_atomic, TranscriptError, TranscriptItem, VideoMetadata};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// What gets stored on disk for one fetched video
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedVideo {
+    metadata: VideoMetadata,
+    transcript: Vec<TranscriptItem>,
+}
+
+// One file per video and variant (language and translation), e.g. dQw4w9WgXcQ_en.json
+fn cache_path(dir: &Path, video_id: &str, variant: &str) -> PathBuf {
+    dir.join(format!("{}_{}.json", video_id, variant))
+}
+
+// Look up a previously fetched video, ignoring entries older than `ttl`
+// Anything unreadable is treated as a miss, the cache is only an optimization
+pub fn read_cache(
+    dir: &Path,
+    video_id: &str,
+    variant: &str,
+    ttl: Duration,
+) -> Option<(VideoMetadata, Vec<TranscriptItem>)> {
+    let path = cache_path(dir, video_id, variant);
+    let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+    let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+    if age > ttl {
+        return None;
+    }
+
+    let cached: CachedVideo = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    Some((cached.metadata, cached.transcript))
+}
+
+pub fn write_cache(
+    dir: &Path,
+    video_id: &str,
+    variant: &str,
+    metadata: &VideoMetadata,
+    transcript: &[TranscriptItem],
+) -> Result<(), TranscriptError> {
+    fs::create_dir_all(dir)?;
+    let cached = CachedVideo {
+        metadata: metadata.clone(),
+        transcript: transcript.to_vec(),
+    };
+    write_atomic(&cache_path(dir, video_id, variant), serde_json::to_string(&cached)?)?;
+    Ok(())
+}
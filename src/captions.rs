@@ -0,0 +1,169 @@
+use crate::TranscriptError;
+use serde::{Deserialize, Serialize};
+
+// What we know about one caption track without downloading it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptionTrackInfo {
+    pub language_code: String,
+    pub name: Option<String>,
+    // Auto-generated (speech recognition) tracks are marked with kind "asr"
+    pub is_auto_generated: bool,
+}
+
+impl CaptionTrackInfo {
+    // Whether the track is in the given language, the same way
+    // select_caption_track matches it: exactly, or as a regional variant
+    pub fn matches_language(&self, code: &str) -> bool {
+        self.language_code == code
+            || self.language_code.split_once('-').is_some_and(|(base, _)| base == code)
+    }
+}
+
+// The captionTracks array of a player response, if the video has one
+pub fn caption_tracks(player_response: &serde_json::Value) -> Option<&Vec<serde_json::Value>> {
+    player_response
+        .get("captions")
+        .and_then(|c| c.get("playerCaptionsTracklistRenderer"))
+        .and_then(|p| p.get("captionTracks"))
+        .and_then(|t| t.as_array())
+}
+
+fn track_language(track: &serde_json::Value) -> Option<&str> {
+    track.get("languageCode").and_then(|c| c.as_str())
+}
+
+// Pick the caption track to download
+// Without a language we take whatever YouTube lists first. With one we look for
+// an exact languageCode match, then a regional variant of it (so "en" finds "en-GB"),
+// and report the codes that do exist if neither is found
+pub fn select_caption_track<'a>(
+    captions: &'a [serde_json::Value],
+    lang: Option<&str>,
+) -> Result<Option<&'a serde_json::Value>, TranscriptError> {
+    let Some(code) = lang else {
+        return Ok(captions.first());
+    };
+
+    let exact = captions.iter().find(|t| track_language(t) == Some(code));
+    let regional = || {
+        captions.iter().find(|t| {
+            track_language(t)
+                .and_then(|l| l.split_once('-'))
+                .is_some_and(|(base, _)| base == code)
+        })
+    };
+
+    match exact.or_else(regional) {
+        Some(track) => Ok(Some(track)),
+        None if captions.is_empty() => Ok(None),
+        None => Err(TranscriptError::LanguageNotAvailable {
+            requested: code.to_string(),
+            available: captions
+                .iter()
+                .filter_map(track_language)
+                .map(str::to_string)
+                .collect(),
+        }),
+    }
+}
+
+// Track names come either as {"simpleText": "English"} or as a list of runs
+fn track_name(track: &serde_json::Value) -> Option<String> {
+    let name = track.get("name")?;
+    if let Some(text) = name.get("simpleText").and_then(|t| t.as_str()) {
+        return Some(text.to_string());
+    }
+    let runs = name.get("runs")?.as_array()?;
+    Some(runs.iter().filter_map(|r| r.get("text").and_then(|t| t.as_str())).collect())
+}
+
+// Describe every caption track listed in a player response
+pub fn parse_caption_tracks(player_response: &serde_json::Value) -> Vec<CaptionTrackInfo> {
+    caption_tracks(player_response)
+        .map(|tracks| {
+            tracks
+                .iter()
+                .filter_map(|track| {
+                    Some(CaptionTrackInfo {
+                        language_code: track_language(track)?.to_string(),
+                        name: track_name(track),
+                        is_auto_generated: track.get("kind").and_then(|k| k.as_str()) == Some("asr"),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> serde_json::Value {
+        let json = include_str!("../tests/fixtures/player_multi_track.json");
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn selected_language(lang: Option<&str>) -> Result<Option<String>, TranscriptError> {
+        let player = fixture();
+        let tracks = caption_tracks(&player).unwrap();
+        Ok(select_caption_track(tracks, lang)?.and_then(track_language).map(str::to_string))
+    }
+
+    #[test]
+    fn picks_the_track_in_the_requested_language() {
+        assert_eq!(selected_language(Some("en")).unwrap().as_deref(), Some("en"));
+        assert_eq!(selected_language(Some("de")).unwrap().as_deref(), Some("de"));
+    }
+
+    #[test]
+    fn finds_a_regional_variant() {
+        assert_eq!(selected_language(Some("es")).unwrap().as_deref(), Some("es-419"));
+    }
+
+    #[test]
+    fn falls_back_to_the_first_track_without_a_language() {
+        assert_eq!(selected_language(None).unwrap().as_deref(), Some("de"));
+    }
+
+    #[test]
+    fn lists_the_available_languages_when_missing() {
+        match selected_language(Some("fr")) {
+            Err(TranscriptError::LanguageNotAvailable { requested, available }) => {
+                assert_eq!(requested, "fr");
+                assert_eq!(available, ["de", "en", "es-419"]);
+            }
+            other => panic!("expected LanguageNotAvailable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn describes_every_track() {
+        let tracks = parse_caption_tracks(&fixture());
+        assert_eq!(
+            tracks,
+            [
+                CaptionTrackInfo {
+                    language_code: "de".to_string(),
+                    name: Some("German".to_string()),
+                    is_auto_generated: false,
+                },
+                CaptionTrackInfo {
+                    language_code: "en".to_string(),
+                    name: Some("English (auto-generated)".to_string()),
+                    is_auto_generated: true,
+                },
+                CaptionTrackInfo {
+                    language_code: "es-419".to_string(),
+                    name: Some("Spanish (Latin America)".to_string()),
+                    is_auto_generated: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_captions_means_no_tracks() {
+        assert!(parse_caption_tracks(&serde_json::json!({ "videoDetails": {} })).is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::http::{read_text, HttpClient};
+use crate::playlist::{collect_video_ids, extract_initial_data};
+use crate::{FetchOptions, TranscriptError};
+use log::info;
+
+pub fn build_channel_videos_url(base_url: &str, handle: &str) -> String {
+    format!("{}/@{}/videos", base_url.trim_end_matches('/'), handle)
+}
+
+// Handles are 3-30 letters, digits, underscores, hyphens and periods
+fn is_valid_handle(handle: &str) -> bool {
+    (3..=30).contains(&handle.len())
+        && handle
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+// Pull the handle (without its @) out of a channel link
+// (https://www.youtube.com/@SomeChannel/videos), or accept "@SomeChannel"
+pub fn parse_channel_handle(input: &str) -> Option<String> {
+    let input = input.trim();
+    let start = input.find('@')?;
+    let handle: String = input[start + 1..]
+        .chars()
+        .take_while(|c| !matches!(c, '/' | '?' | '#'))
+        .collect();
+    is_valid_handle(&handle).then_some(handle)
+}
+
+// The uploads listed in a channel videos tab's ytInitialData, newest first
+// Like playlists, the renderers sit deep inside tabs whose layout changes,
+// so the whole tree is searched. A channel without public uploads gives none
+pub fn parse_channel_video_ids(initial_data: &serde_json::Value) -> Vec<String> {
+    // The videos tab lists uploads as videoRenderer entries, older layouts as
+    // gridVideoRenderer ones
+    let mut ids = Vec::new();
+    collect_video_ids(initial_data, &["videoRenderer", "gridVideoRenderer"], &mut ids);
+    ids
+}
+
+// Fetch a channel's videos tab and list the IDs of its latest uploads, at most
+// `limit` of them. Only the first page load is seen (about 30 videos), the same
+// as with crate::fetch_playlist_video_ids
+pub async fn fetch_channel_video_ids(
+    handle: &str,
+    limit: Option<usize>,
+    options: &FetchOptions,
+) -> Result<Vec<String>, TranscriptError> {
+    let client = HttpClient::new(options)?;
+
+    info!("Fetching channel page...");
+    let response = client.get(&build_channel_videos_url(options.base_url(), handle)).await?;
+    let html = read_text(response, options.max_page_size).await?;
+
+    let json_str = extract_initial_data(&html).ok_or(TranscriptError::PlayerDataNotFound)?;
+    let initial_data: serde_json::Value = serde_json::from_str(json_str)?;
+
+    let mut ids = parse_channel_video_ids(&initial_data);
+    if let Some(limit) = limit {
+        ids.truncate(limit);
+    }
+    info!("Found {} videos on channel @{}", ids.len(), handle);
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+
+    #[test]
+    fn lists_the_uploads_of_a_captured_channel_page() {
+        let html = include_str!("../tests/fixtures/channel_page.html");
+        let initial_data: serde_json::Value =
+            serde_json::from_str(extract_initial_data(html).unwrap()).unwrap();
+        assert_eq!(
+            parse_channel_video_ids(&initial_data),
+            ["dddddddddd4", "eeeeeeeeee5", "fffffffff-6"]
+        );
+    }
+
+    #[test]
+    fn parses_channel_links_and_handles() {
+        let handle = Some("SomeChannel".to_string());
+        assert_eq!(parse_channel_handle("https://www.youtube.com/@SomeChannel/videos"), handle);
+        assert_eq!(parse_channel_handle("@SomeChannel"), handle);
+        assert_eq!(parse_channel_handle("@ab"), None);
+        assert_eq!(parse_channel_handle("SomeChannel"), None);
+    }
+
+    #[tokio::test]
+    async fn fetches_the_latest_uploads() {
+        let server = MockServer::start();
+        server.mock(
+            "/@SomeChannel/videos",
+            MockResponse::ok(include_str!("../tests/fixtures/channel_page.html")),
+        );
+        let options = FetchOptions {
+            base_url: server.url().to_string(),
+            ..FetchOptions::default()
+        };
+        let ids = fetch_channel_video_ids("SomeChannel", Some(2), &options).await.unwrap();
+        assert_eq!(ids, ["dddddddddd4", "eeeeeeeeee5"]);
+    }
+}
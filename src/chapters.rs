@@ -0,0 +1,142 @@
+use crate::{extract_initial_data, parse_timestamp};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// A chapter of the video: its title and when it starts, in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chapter {
+    pub title: String,
+    pub start: f64,
+}
+
+fn simple_text(value: &Value) -> Option<String> {
+    value
+        .get("simpleText")
+        .and_then(|t| t.as_str())
+        .map(|t| t.trim().to_string())
+}
+
+// Chapter markers appear as chapterRenderer objects in the player bar and as
+// macroMarkersListItemRenderer objects in the chapter panel, nested deep inside
+// ytInitialData, so the whole tree is searched for either
+fn collect_markers(value: &Value, chapters: &mut Vec<Chapter>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(renderer) = map.get("chapterRenderer") {
+                let start = renderer.get("timeRangeStartMillis").and_then(|ms| ms.as_f64());
+                if let (Some(title), Some(start)) = (renderer.get("title").and_then(simple_text), start) {
+                    chapters.push(Chapter {
+                        title,
+                        start: start / 1000.0,
+                    });
+                }
+            }
+            if let Some(renderer) = map.get("macroMarkersListItemRenderer") {
+                let start = renderer
+                    .pointer("/onTap/watchEndpoint/startTimeSeconds")
+                    .and_then(|s| s.as_f64());
+                if let (Some(title), Some(start)) = (renderer.get("title").and_then(simple_text), start) {
+                    chapters.push(Chapter { title, start });
+                }
+            }
+            for child in map.values() {
+                collect_markers(child, chapters);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_markers(item, chapters);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Read the chapter markers from a watch page's ytInitialData
+// Both places YouTube lists them usually hold the same chapters, so duplicates
+// starting at the same time are dropped
+pub fn parse_chapter_markers(html: &str) -> Vec<Chapter> {
+    let Some(initial_data) = extract_initial_data(html).and_then(|json| serde_json::from_str(json).ok()) else {
+        return Vec::new();
+    };
+
+    let mut chapters = Vec::new();
+    collect_markers(&initial_data, &mut chapters);
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+    chapters.dedup_by(|a, b| a.start == b.start);
+    chapters
+}
+
+// Read chapters from a video description, where they're written as lines like
+// "0:00 Intro" or "1:02:03 - Q&A". Like YouTube itself, this only counts them
+// as chapters when the first one starts at 0:00 and there are at least three
+pub fn parse_description_chapters(description: &str) -> Vec<Chapter> {
+    let chapters: Vec<Chapter> = description
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let (time, title) = line.split_once(char::is_whitespace)?;
+            if !time.contains(':') {
+                return None;
+            }
+            let start = parse_timestamp(time)?;
+            let title = title.trim_start_matches([' ', '-', '\u{2013}', '\u{2014}', ':', '|']).trim();
+            (!title.is_empty()).then(|| Chapter {
+                title: title.to_string(),
+                start,
+            })
+        })
+        .collect();
+
+    if chapters.len() >= 3 && chapters[0].start == 0.0 {
+        chapters
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapters(list: &[(&str, f64)]) -> Vec<Chapter> {
+        list.iter()
+            .map(|(title, start)| Chapter {
+                title: title.to_string(),
+                start: *start,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_both_kinds_of_chapter_markers() {
+        let html = include_str!("../tests/fixtures/watch_page_chapters.html");
+        assert_eq!(
+            parse_chapter_markers(html),
+            chapters(&[
+                ("Intro", 0.0),
+                ("Setting up", 65.0),
+                ("Questions", 1800.0),
+                ("Wrapping up", 3725.5),
+            ])
+        );
+    }
+
+    #[test]
+    fn a_page_without_markers_has_no_chapters() {
+        let html = "<html><script>var ytInitialData = {};</script></html>";
+        assert!(parse_chapter_markers(html).is_empty());
+        assert!(parse_chapter_markers("<html></html>").is_empty());
+    }
+
+    #[test]
+    fn description_chapters_need_to_start_at_zero() {
+        let description = "Links below\n0:00 Intro\n1:05 - Setting up\n1:02:05.5 \u{2013} Q&A\n";
+        assert_eq!(
+            parse_description_chapters(description),
+            chapters(&[("Intro", 0.0), ("Setting up", 65.0), ("Q&A", 3725.5)])
+        );
+        assert!(parse_description_chapters("0:10 Intro\n1:05 Middle\n2:00 End\n").is_empty());
+        assert!(parse_description_chapters("0:00 Intro\n1:05 End\n").is_empty());
+    }
+}
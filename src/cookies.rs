@@ -0,0 +1,98 @@
+use crate::TranscriptError;
+use reqwest::cookie::Jar;
+use std::fs;
+use std::path::Path;
+
+// One cookie line from a Netscape cookies.txt file
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetscapeCookie {
+    pub domain: String,
+    pub path: String,
+    pub secure: bool,
+    pub name: String,
+    pub value: String,
+}
+
+// Parse the tab-separated cookies.txt format written by browser extensions and curl:
+// domain, include-subdomains flag, path, secure, expiry, name, value
+// Comment and blank lines are skipped, except for the "#HttpOnly_" prefix some
+// exporters put in front of the domain of HttpOnly cookies
+pub fn parse_netscape_cookies(content: &str) -> Vec<NetscapeCookie> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_end_matches('\r');
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 7 {
+                return None;
+            }
+
+            Some(NetscapeCookie {
+                domain: fields[0].to_string(),
+                path: fields[2].to_string(),
+                secure: fields[3].eq_ignore_ascii_case("TRUE"),
+                name: fields[5].to_string(),
+                value: fields[6].to_string(),
+            })
+        })
+        .collect()
+}
+
+// Build a cookie jar for reqwest from a cookies.txt file
+pub fn load_cookie_jar(path: &Path) -> Result<Jar, TranscriptError> {
+    let content = fs::read_to_string(path)?;
+    let jar = Jar::default();
+
+    for cookie in parse_netscape_cookies(&content) {
+        let host = cookie.domain.trim_start_matches('.');
+        let url = format!("https://{}{}", host, cookie.path)
+            .parse::<reqwest::Url>()
+            .map_err(|e| TranscriptError::Parse(format!("cookie domain {:?}: {}", cookie.domain, e)))?;
+
+        let mut header = format!(
+            "{}={}; Domain={}; Path={}",
+            cookie.name, cookie.value, cookie.domain, cookie.path
+        );
+        if cookie.secure {
+            header.push_str("; Secure");
+        }
+        jar.add_cookie_str(&header, &url);
+    }
+
+    Ok(jar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::cookie::CookieStore;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/cookies.txt");
+
+    #[test]
+    fn parses_cookie_lines_and_skips_comments() {
+        let cookies = parse_netscape_cookies(&fs::read_to_string(FIXTURE).unwrap());
+        let names: Vec<&str> = cookies.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["SID", "__Secure-3PSID", "PREF"]);
+        assert_eq!(cookies[0].domain, ".youtube.com");
+        assert!(cookies[1].secure);
+        assert!(!cookies[2].secure);
+        assert_eq!(cookies[2].value, "f6=40000000");
+    }
+
+    #[test]
+    fn fills_the_cookie_jar() {
+        let jar = load_cookie_jar(Path::new(FIXTURE)).unwrap();
+        let url = "https://www.youtube.com/watch?v=dQw4w9WgXcQ".parse().unwrap();
+        let header = jar.cookies(&url).unwrap();
+        let header = header.to_str().unwrap();
+        for cookie in ["SID=sid-value", "__Secure-3PSID=secure-sid", "PREF=f6=40000000"] {
+            assert!(header.contains(cookie), "{} missing from {}", cookie, header);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use thiserror::Error;
+
+// Everything that can go wrong while fetching or saving a transcript, split
+// out so callers can tell "no captions" apart from a network failure
+#[derive(Debug, Error)]
+pub enum TranscriptError {
+    #[error("Not a valid video ID: {0:?}")]
+    InvalidVideoId(String),
+
+    #[error("Expected the watch page but was redirected to {url}")]
+    UnexpectedRedirect { url: String },
+
+    #[error("Cannot find player data")]
+    PlayerDataNotFound,
+
+    #[error("No captions found for this video")]
+    NoCaptionTracks,
+
+    #[error("Captions are disabled for this video")]
+    CaptionsDisabled,
+
+    #[error("Video unavailable: {reason}")]
+    VideoUnavailable { reason: String },
+
+    #[error("This video is private")]
+    Private,
+
+    #[error("Live streams and upcoming premieres have no transcript yet, try again once it has ended")]
+    LiveStreamUnsupported,
+
+    #[error("Video requires signing in ({reason}); cookies from a signed-in browser may help")]
+    LoginRequired { reason: String },
+
+    #[error("No captions in language {requested:?} (available: {})", available.join(", "))]
+    LanguageNotAvailable {
+        requested: String,
+        available: Vec<String>,
+    },
+
+    #[error("Captions in {language:?} can't be translated")]
+    NotTranslatable { language: String },
+
+    #[error("The range ends ({end}s) before it starts ({start}s)")]
+    InvalidRange { start: f64, end: f64 },
+
+    #[error("The caption response was empty")]
+    EmptyCaptionResponse,
+
+    #[error("No transcript lines found in the response")]
+    EmptyTranscript,
+
+    #[error("The response was larger than the {limit} byte limit")]
+    ResponseTooLarge { limit: usize },
+
+    #[error("Request failed with HTTP status {code}: {snippet}")]
+    BadStatus { code: u16, snippet: String },
+
+    #[error("HTTP request failed: {0}")]
+    Http(reqwest::Error),
+
+    #[error("Request timed out")]
+    Timeout,
+
+    #[error("Failed to parse {0}")]
+    Parse(String),
+
+    #[error("Invalid player data: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("File error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+// A timeout is worth telling apart from other network trouble, so it gets its
+// own variant instead of being wrapped like every other reqwest error
+impl From<reqwest::Error> for TranscriptError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            TranscriptError::Timeout
+        } else {
+            TranscriptError::Http(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prints_like_any_other_error() {
+        let error: Box<dyn std::error::Error> = Box::new(TranscriptError::LanguageNotAvailable {
+            requested: "fr".to_string(),
+            available: vec!["en".to_string(), "es".to_string()],
+        });
+        assert_eq!(error.to_string(), "No captions in language \"fr\" (available: en, es)");
+    }
+
+    #[test]
+    fn io_and_json_errors_convert() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert!(matches!(TranscriptError::from(io), TranscriptError::Io(_)));
+
+        let json = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        assert!(matches!(TranscriptError::from(json), TranscriptError::Json(_)));
+    }
+
+    #[test]
+    fn kinds_can_be_matched_on() {
+        let error = crate::validate_video_id("bad").unwrap_err();
+        assert!(matches!(error, TranscriptError::InvalidVideoId(id) if id == "bad"));
+    }
+}
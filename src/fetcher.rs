@@ -0,0 +1,213 @@
+// A builder bundling the language with the FetchOptions, for callers that would
+// rather chain setters than fill in the struct and pass both around:
+//
+//     let transcript = TranscriptFetcher::new().lang("en").retries(5).fetch(id).await?;
+use crate::http::{HttpClient, ResponseMemo};
+use crate::{
+    fetch_raw_captions_with_client, fetch_video_all_langs_with_client, fetch_video_with_client,
+    fetch_word_timings_with_client, list_captions_with_client, CaptionTrackInfo, FetchOptions,
+    LangTranscript, TranscriptError, TranscriptItem, VideoMetadata, WordTiming,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+
+// A fetcher remembers the caption tracks it has downloaded, and so do its
+// clones, so asking for the same track twice only downloads it once
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptFetcher {
+    lang: Option<String>,
+    options: FetchOptions,
+    memo: ResponseMemo,
+}
+
+impl TranscriptFetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Start from existing options instead of the defaults
+    pub fn with_options(options: FetchOptions) -> Self {
+        TranscriptFetcher {
+            lang: None,
+            options,
+            memo: ResponseMemo::default(),
+        }
+    }
+
+    // Prefer the caption track in this language
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = Some(lang.into());
+        self
+    }
+
+    pub fn translate(mut self, target: impl Into<String>) -> Self {
+        self.options.translate = Some(target.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.options.retries = retries;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.options.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.options.proxy = Some(proxy.into());
+        self
+    }
+
+    pub fn cookies(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.cookies = Some(path.into());
+        self
+    }
+
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.cache_dir = Some(dir.into());
+        self
+    }
+
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.options.cache_ttl = ttl;
+        self
+    }
+
+    pub fn min_confidence(mut self, min_confidence: f64) -> Self {
+        self.options.min_confidence = Some(min_confidence);
+        self
+    }
+
+    // Only fetch the first `limit` entries of the transcript
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.options.limit = Some(limit);
+        self
+    }
+
+    // Send the page requests somewhere other than https://www.youtube.com
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.options.base_url = base_url.into();
+        self
+    }
+
+    pub fn use_api(mut self, use_api: bool) -> Self {
+        self.options.use_api = use_api;
+        self
+    }
+
+    pub fn max_page_size(mut self, bytes: usize) -> Self {
+        self.options.max_page_size = bytes;
+        self
+    }
+
+    pub fn max_caption_size(mut self, bytes: usize) -> Self {
+        self.options.max_caption_size = bytes;
+        self
+    }
+
+    pub fn options(&self) -> &FetchOptions {
+        &self.options
+    }
+
+    fn client(&self) -> Result<HttpClient, TranscriptError> {
+        HttpClient::with_memo(&self.options, self.memo.clone())
+    }
+
+    pub async fn fetch(&self, video_id: &str) -> Result<Vec<TranscriptItem>, TranscriptError> {
+        let (_, transcript) = self.fetch_video(video_id).await?;
+        Ok(transcript)
+    }
+
+    // The transcript together with the video's metadata, see crate::fetch_video
+    pub async fn fetch_video(
+        &self,
+        video_id: &str,
+    ) -> Result<(VideoMetadata, Vec<TranscriptItem>), TranscriptError> {
+        fetch_video_with_client(&self.client()?, video_id, self.lang.as_deref()).await
+    }
+
+    // Every caption language of the video, whatever lang is set to, see
+    // crate::fetch_video_all_langs
+    pub async fn fetch_all_langs(
+        &self,
+        video_id: &str,
+    ) -> Result<(VideoMetadata, Vec<LangTranscript>), TranscriptError> {
+        fetch_video_all_langs_with_client(&self.client()?, video_id).await
+    }
+
+    pub async fn word_timings(&self, video_id: &str) -> Result<Vec<WordTiming>, TranscriptError> {
+        fetch_word_timings_with_client(&self.client()?, video_id, self.lang.as_deref()).await
+    }
+
+    // The caption XML as YouTube sent it, see crate::fetch_raw_captions
+    pub async fn raw_captions(&self, video_id: &str) -> Result<Vec<u8>, TranscriptError> {
+        fetch_raw_captions_with_client(&self.client()?, video_id, self.lang.as_deref()).await
+    }
+
+    pub async fn list_captions(
+        &self,
+        video_id: &str,
+    ) -> Result<Vec<CaptionTrackInfo>, TranscriptError> {
+        list_captions_with_client(&self.client()?, video_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{json3_body, player_response, watch_page, MockResponse, MockServer};
+
+    #[tokio::test]
+    async fn fetches_with_the_configured_settings() {
+        let server = MockServer::start();
+        let player = player_response(server.url(), "dQw4w9WgXcQ", &["en", "es"]);
+        server.mock("/watch?v=dQw4w9WgXcQ", MockResponse::ok(watch_page(&player)));
+        for (lang, text) in [("en", "hello"), ("es", "hola")] {
+            server.mock(
+                &format!("/api/timedtext?v=dQw4w9WgXcQ&lang={}&fmt=json3", lang),
+                MockResponse::ok(json3_body(&[(0, 1000, text)])),
+            );
+        }
+
+        let fetcher = TranscriptFetcher::new()
+            .lang("es")
+            .user_agent("transcript-test/1.0")
+            .retries(0)
+            .timeout(Duration::from_secs(5))
+            .base_url(server.url());
+        assert_eq!(fetcher.options().retries, 0);
+        assert_eq!(fetcher.options().timeout, Duration::from_secs(5));
+
+        let transcript = fetcher.fetch("dQw4w9WgXcQ").await.unwrap();
+        assert_eq!(transcript.len(), 1);
+        assert_eq!(transcript[0].text, "hola");
+        let requests = server.requests();
+        assert!(requests.iter().all(|r| r.header("user-agent") == Some("transcript-test/1.0")));
+        assert_eq!(server.hits("/api/timedtext?v=dQw4w9WgXcQ&lang=en&fmt=json3"), 0);
+    }
+
+    #[tokio::test]
+    async fn a_fetcher_downloads_a_track_once_and_another_fetcher_again() {
+        let server = MockServer::start();
+        crate::mock_server::mock_video(&server, "dQw4w9WgXcQ", &["en"], &json3_body(&[(0, 1000, "hi")]));
+        let captions = "/api/timedtext?v=dQw4w9WgXcQ&lang=en&fmt=json3";
+        let fetcher = TranscriptFetcher::new().base_url(server.url());
+        fetcher.fetch("dQw4w9WgXcQ").await.unwrap();
+        fetcher.clone().retries(1).word_timings("dQw4w9WgXcQ").await.unwrap();
+        assert_eq!(server.hits(captions), 1);
+        // The watch page itself isn't kept
+        assert_eq!(server.hits("/watch"), 2);
+
+        TranscriptFetcher::new().base_url(server.url()).fetch("dQw4w9WgXcQ").await.unwrap();
+        assert_eq!(server.hits(captions), 2);
+        crate::fetch_video("dQw4w9WgXcQ", None, fetcher.options()).await.unwrap();
+        assert_eq!(server.hits(captions), 3);
+    }
+}
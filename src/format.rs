@@ -0,0 +1,373 @@
+use crate::{collapse_whitespace, TranscriptError, TranscriptItem, VideoMetadata};
+use serde::Serialize;
+
+// Render a time in seconds as HH:MM:SS followed by the given separator and
+// milliseconds, e.g. 3725.5 with ',' becomes "01:02:05,500"
+fn format_cue_time(seconds: f64, separator: char) -> String {
+    let total_ms = (seconds.max(0.0) * 1000.0).round() as u64;
+    let hours = total_ms / 3_600_000;
+    let mins = (total_ms % 3_600_000) / 60_000;
+    let secs = (total_ms % 60_000) / 1000;
+    let millis = total_ms % 1000;
+    format!("{:02}:{:02}:{:02}{}{:03}", hours, mins, secs, separator, millis)
+}
+
+// Convert a transcript into SubRip (SRT) subtitles
+// Each entry becomes a numbered cue running from start to start + duration:
+//
+// 1
+// 00:00:01,200 --> 00:00:03,450
+// First line of transcript
+pub fn to_srt(transcript: &[TranscriptItem]) -> String {
+    render_cues(transcript, srt_cue).join("\n")
+}
+
+fn srt_cue(i: usize, item: &TranscriptItem) -> String {
+    format!(
+        "{}\n{} --> {}\n{}\n",
+        i + 1,
+        format_cue_time(item.start, ','),
+        format_cue_time(item.end(), ','),
+        item.text
+    )
+}
+
+// Convert a transcript into WebVTT subtitles for HTML5 <track> elements
+// Cues with a zero (or negative) duration are stretched to one millisecond,
+// since WebVTT requires every cue to end after it starts
+pub fn to_vtt(transcript: &[TranscriptItem]) -> String {
+    format!("WEBVTT\n{}", render_cues(transcript, vtt_cue).concat())
+}
+
+fn vtt_cue(_: usize, item: &TranscriptItem) -> String {
+    let end = item.end().max(item.start + 0.001);
+    format!(
+        "\n{} --> {}\n{}\n",
+        format_cue_time(item.start, '.'),
+        format_cue_time(end, '.'),
+        item.text
+    )
+}
+
+// Render every entry's cue with its position in the transcript, in order
+// With the "parallel" feature the cues are built on all cores; collecting an
+// indexed parallel iterator keeps them in their original order
+#[cfg(feature = "parallel")]
+fn render_cues<F>(transcript: &[TranscriptItem], cue: F) -> Vec<String>
+where
+    F: Fn(usize, &TranscriptItem) -> String + Sync,
+{
+    use rayon::prelude::*;
+    transcript.par_iter().enumerate().map(|(i, item)| cue(i, item)).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn render_cues<F>(transcript: &[TranscriptItem], cue: F) -> Vec<String>
+where
+    F: Fn(usize, &TranscriptItem) -> String,
+{
+    render_cues_serially(transcript, cue)
+}
+
+// The cues one after another on this thread, which the parallel build's tests
+// compare against
+#[cfg_attr(all(feature = "parallel", not(test)), allow(dead_code))]
+fn render_cues_serially<F>(transcript: &[TranscriptItem], cue: F) -> Vec<String>
+where
+    F: Fn(usize, &TranscriptItem) -> String,
+{
+    transcript.iter().enumerate().map(|(i, item)| cue(i, item)).collect()
+}
+
+// Serialize the transcript as pretty-printed JSON, keeping the raw float
+// timings so programmatic consumers get full precision
+pub fn to_json(transcript: &[TranscriptItem]) -> Result<String, TranscriptError> {
+    Ok(serde_json::to_string_pretty(transcript)?)
+}
+
+// Serialize the transcript as JSON Lines: one compact object per entry, each on
+// its own line, so tools like jq can process it line by line
+pub fn to_jsonl(transcript: &[TranscriptItem]) -> Result<String, TranscriptError> {
+    let mut output = String::new();
+    for item in transcript {
+        output.push_str(&serde_json::to_string(item)?);
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+#[derive(Serialize)]
+struct JsonDocument<'a> {
+    metadata: &'a VideoMetadata,
+    transcript: &'a [TranscriptItem],
+}
+
+// Same as to_json, but wraps the entries in an object next to the video's metadata:
+// {"metadata": {...}, "transcript": [...]}
+pub fn to_json_with_metadata(
+    metadata: &VideoMetadata,
+    transcript: &[TranscriptItem],
+) -> Result<String, TranscriptError> {
+    Ok(serde_json::to_string_pretty(&JsonDocument { metadata, transcript })?)
+}
+
+// One line per entry with a millisecond-precise timestamp: [01:05.250] text
+// Unlike the normalized text nothing is grouped, since that would round the timings away
+pub fn to_precise_text(transcript: &[TranscriptItem]) -> String {
+    transcript
+        .iter()
+        .map(|item| format!("{} {}\n", item.format_time_precise(), collapse_whitespace(&item.text)))
+        .collect()
+}
+
+// One line per entry with its duration next to the timestamp: [01:05 +3.2s] text
+pub fn to_text_with_durations(transcript: &[TranscriptItem]) -> String {
+    transcript
+        .iter()
+        .map(|item| {
+            let time = item.format_time();
+            format!(
+                "{} +{:.1}s] {}\n",
+                time.trim_end_matches(']'),
+                item.duration,
+                collapse_whitespace(&item.text)
+            )
+        })
+        .collect()
+}
+
+// Join just the caption text into running prose, with no timestamps at all
+// Handy for feeding transcripts into text analysis where [MM:SS] markers are noise
+pub fn to_plain_text(transcript: &[TranscriptItem]) -> String {
+    let text = transcript
+        .iter()
+        .map(|item| collapse_whitespace(&item.text))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("{}\n", text)
+}
+
+// Convert a transcript into CSV rows of start,duration,end,text with a header row
+// The csv crate takes care of quoting text that contains commas, quotes or newlines
+pub fn to_csv(transcript: &[TranscriptItem]) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    // Writing into a Vec can't fail, so the results below are safe to unwrap
+    writer
+        .write_record(["start", "duration", "end", "text"])
+        .expect("writing CSV to memory");
+    for item in transcript {
+        writer
+            .write_record([
+                item.start.to_string(),
+                item.duration.to_string(),
+                item.end().to_string(),
+                item.text.clone(),
+            ])
+            .expect("writing CSV to memory");
+    }
+    let bytes = writer.into_inner().expect("writing CSV to memory");
+    String::from_utf8(bytes).expect("CSV built from UTF-8 strings")
+}
+
+// Convert a transcript into a Markdown list where every timestamp links to that
+// moment of the video: - [01:05](https://youtu.be/VIDEO_ID?t=65) text
+pub fn to_markdown(transcript: &[TranscriptItem], video_id: &str) -> String {
+    transcript
+        .iter()
+        .map(|item| {
+            format!(
+                "- {}(https://youtu.be/{}?t={}) {}\n",
+                item.format_time(),
+                video_id,
+                item.start.max(0.0).floor() as u64,
+                item.text.trim()
+            )
+        })
+        .collect()
+}
+
+// Hard-wrap every line of plain text output at `width` columns, breaking between
+// words. A leading "[MM:SS] " timestamp stays on the first line and continuation
+// lines are indented to line up with the text after it. Words longer than the
+// width are kept whole on a line of their own
+pub fn wrap_lines(text: &str, width: usize) -> String {
+    let mut wrapped = String::with_capacity(text.len());
+
+    for line in text.lines() {
+        let prefix_len = match line.find("] ") {
+            Some(idx) if line.starts_with('[') => idx + 2,
+            _ => 0,
+        };
+        let (prefix, body) = line.split_at(prefix_len);
+        let indent = " ".repeat(prefix.chars().count());
+
+        let mut current = prefix.to_string();
+        let mut current_len = prefix.chars().count();
+        let mut has_word = false;
+        for word in body.split_whitespace() {
+            let word_len = word.chars().count();
+            if has_word && current_len + 1 + word_len > width {
+                wrapped.push_str(&current);
+                wrapped.push('\n');
+                current = indent.clone();
+                current_len = indent.len();
+                has_word = false;
+            }
+            if has_word {
+                current.push(' ');
+                current_len += 1;
+            }
+            current.push_str(word);
+            current_len += word_len;
+            has_word = true;
+        }
+        wrapped.push_str(current.trim_end());
+        wrapped.push('\n');
+    }
+
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(text: &str, start: f64, duration: f64) -> TranscriptItem {
+        TranscriptItem {
+            text: text.to_string(),
+            start,
+            duration,
+            index: 0,
+        }
+    }
+
+    #[test]
+    fn srt_numbers_cues_and_times_them_to_the_millisecond() {
+        let transcript = [item("Hello there", 1.2, 2.25), item("General Kenobi", 3725.5, 1.0)];
+        assert_eq!(
+            to_srt(&transcript),
+            "1\n00:00:01,200 --> 00:00:03,450\nHello there\n\n\
+             2\n01:02:05,500 --> 01:02:06,500\nGeneral Kenobi\n"
+        );
+    }
+
+    #[test]
+    fn srt_of_nothing_is_empty() {
+        assert_eq!(to_srt(&[]), "");
+    }
+
+    #[test]
+    fn vtt_has_the_header_and_dotted_milliseconds() {
+        let vtt = to_vtt(&[item("Hello", 61.05, 2.0)]);
+        assert_eq!(vtt, "WEBVTT\n\n00:01:01.050 --> 00:01:03.050\nHello\n");
+    }
+
+    #[test]
+    fn vtt_never_inverts_a_zero_duration_cue() {
+        let vtt = to_vtt(&[item("Blink", 5.0, 0.0)]);
+        assert!(vtt.contains("00:00:05.000 --> 00:00:05.001"), "{}", vtt);
+    }
+
+    #[test]
+    fn json_round_trips_with_full_precision() {
+        let transcript = vec![
+            TranscriptItem { index: 0, ..item("First", 0.123456789, 2.5) },
+            TranscriptItem { index: 1, ..item("Second \"quoted\"", 2.623456789, 1.0 / 3.0) },
+        ];
+        let json = to_json(&transcript).unwrap();
+        assert!(json.contains('\n'), "expected pretty-printed JSON");
+        let back: Vec<TranscriptItem> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, transcript);
+    }
+
+    #[test]
+    fn plain_text_has_no_timestamps() {
+        let transcript = [item("still\nhere ", 65.0, 1.0), item("", 66.0, 1.0), item(" done", 67.0, 1.0)];
+        let text = to_plain_text(&transcript);
+        assert_eq!(text, "still here done\n");
+        assert!(!text.contains('['));
+    }
+
+    #[test]
+    fn csv_quotes_commas_and_quotes() {
+        let csv = to_csv(&[item("Well, he said \"hi\"", 1.5, 2.0)]);
+        assert_eq!(csv, "start,duration,end,text\n1.5,2,3.5,\"Well, he said \"\"hi\"\"\"\n");
+
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let record = reader.records().next().unwrap().unwrap();
+        assert_eq!(&record[3], "Well, he said \"hi\"");
+    }
+
+    #[test]
+    fn markdown_links_to_the_whole_second() {
+        let markdown = to_markdown(&[item("Intro", 0.4, 1.0), item("Later", 65.99, 1.0)], "dQw4w9WgXcQ");
+        assert_eq!(
+            markdown,
+            "- [00:00](https://youtu.be/dQw4w9WgXcQ?t=0) Intro\n\
+             - [01:05](https://youtu.be/dQw4w9WgXcQ?t=65) Later\n"
+        );
+    }
+
+    #[test]
+    fn wrapping_indents_continuations_under_the_text() {
+        let line = "[00:05] The quick brown fox jumps over the lazy dog and keeps on running far away\n";
+        assert_eq!(
+            wrap_lines(line, 40),
+            "[00:05] The quick brown fox jumps over\n\
+             \x20       the lazy dog and keeps on\n\
+             \x20       running far away\n"
+        );
+        assert!(wrap_lines(line, 40).lines().all(|l| l.chars().count() <= 40));
+    }
+
+    #[test]
+    fn wrapping_keeps_long_words_whole_and_short_lines_alone() {
+        assert_eq!(wrap_lines("[00:01] hi there\n", 40), "[00:01] hi there\n");
+        assert_eq!(
+            wrap_lines("no timestamp supercalifragilistic\n", 10),
+            "no\ntimestamp\nsupercalifragilistic\n"
+        );
+    }
+
+    #[test]
+    fn durations_sit_inside_the_timestamp_to_a_tenth_of_a_second() {
+        let transcript = [item("Hello  there", 65.4, 3.2), item("Bye", 70.0, 0.04)];
+        assert_eq!(
+            to_text_with_durations(&transcript),
+            "[01:05 +3.2s] Hello there\n[01:10 +0.0s] Bye\n"
+        );
+    }
+
+    #[test]
+    fn jsonl_has_one_independent_object_per_line() {
+        let transcript = [item("First\nline", 0.5, 2.0), item("Second, \"quoted\"", 2.5, 1.0), item("Third", 3.5, 1.0)];
+        let jsonl = to_jsonl(&transcript).unwrap();
+        let lines: Vec<&str> = jsonl.lines().collect();
+        assert_eq!(lines.len(), transcript.len());
+        for (line, original) in lines.iter().zip(&transcript) {
+            let parsed: TranscriptItem = serde_json::from_str(line).unwrap();
+            assert_eq!(&parsed, original);
+            assert!(serde_json::from_str::<serde_json::Value>(line).unwrap().is_object());
+        }
+        assert!(jsonl.ends_with("}\n"));
+    }
+
+    #[test]
+    fn cues_come_out_in_order_however_they_are_built() {
+        let transcript: Vec<TranscriptItem> = (0..20_000)
+            .map(|i| TranscriptItem {
+                index: i,
+                ..item(&format!("cue {}", i), i as f64 * 1.5, if i % 7 == 0 { 0.0 } else { 1.25 })
+            })
+            .collect();
+        // With the parallel feature render_cues runs on rayon's pool, without it
+        // this checks the serial path against itself
+        for cue in [srt_cue, vtt_cue] {
+            assert_eq!(render_cues(&transcript, cue), render_cues_serially(&transcript, cue));
+        }
+        let srt = to_srt(&transcript);
+        assert!(srt.starts_with("1\n00:00:00,000 --> 00:00:00,000\ncue 0\n\n2\n00:00:01,500"));
+        assert!(srt.ends_with("20000\n08:19:58,500 --> 08:19:58,500\ncue 19999\n"));
+    }
+}
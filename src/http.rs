@@ -0,0 +1,551 @@
+use crate::{load_cookie_jar, TranscriptError};
+use log::{debug, warn};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Proxy, RequestBuilder, Response, StatusCode};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// Where YouTube is reached unless FetchOptions::base_url says otherwise
+pub const DEFAULT_BASE_URL: &str = "https://www.youtube.com";
+
+// The browser-like User-Agent sent when FetchOptions::user_agent isn't set
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36";
+
+// How many redirects a request may follow before it's treated as a loop
+// Region and consent bounces take two or three, never anywhere near this many
+const MAX_REDIRECTS: usize = 10;
+
+// The redirect policy shared by the async and blocking clients
+pub(crate) fn redirect_policy() -> Policy {
+    Policy::limited(MAX_REDIRECTS)
+}
+
+// Default body size limits. Watch pages run to about 1 MB and caption tracks
+// rarely pass a few MB even for very long videos
+pub const DEFAULT_MAX_PAGE_SIZE: usize = 10 * 1024 * 1024;
+pub const DEFAULT_MAX_CAPTION_SIZE: usize = 32 * 1024 * 1024;
+
+// Settings for fetching a transcript and the HTTP requests involved
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    // Scheme and host the watch, playlist, channel and player API requests go
+    // to, e.g. a local server standing in for YouTube. Caption tracks are
+    // downloaded from wherever the player response points
+    pub base_url: String,
+    // How many times a failed request is retried before giving up
+    pub retries: u32,
+    // How long a single request may take before it's abandoned
+    pub timeout: Duration,
+    // Proxy for every request, e.g. "http://proxy:8080" or "socks5://127.0.0.1:1080"
+    // Without one, reqwest falls back to the HTTP_PROXY / HTTPS_PROXY environment variables
+    pub proxy: Option<String>,
+    // Netscape-format cookies.txt to send along, e.g. from a signed-in browser
+    // session so age-restricted videos expose their captions
+    pub cookies: Option<PathBuf>,
+    // Directory where fetched transcripts are cached, None disables caching
+    // The blocking client never caches
+    pub cache_dir: Option<PathBuf>,
+    // How long a cached transcript stays valid
+    pub cache_ttl: Duration,
+    // Language code to machine-translate the captions into
+    pub translate: Option<String>,
+    // User-Agent header to send instead of DEFAULT_USER_AGENT
+    pub user_agent: Option<String>,
+    // Drop speech recognition segments less confident than this (0.0-1.0)
+    // Only auto-generated json3 tracks carry confidence values
+    pub min_confidence: Option<f64>,
+    // Stop after this many entries, reading no more of the caption track than it
+    // takes to parse them
+    pub limit: Option<usize>,
+    // Ask YouTube's player API for the caption data instead of scraping the
+    // watch page (which also falls back to the API). Only the async client does
+    pub use_api: bool,
+    // Largest watch page, playlist page or player API response read, in bytes
+    pub max_page_size: usize,
+    // Largest caption track read, in bytes
+    pub max_caption_size: usize,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        FetchOptions {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            retries: 3,
+            timeout: Duration::from_secs(30),
+            proxy: None,
+            cookies: None,
+            cache_dir: None,
+            cache_ttl: Duration::from_secs(24 * 60 * 60),
+            translate: None,
+            user_agent: None,
+            min_confidence: None,
+            limit: None,
+            use_api: false,
+            max_page_size: DEFAULT_MAX_PAGE_SIZE,
+            max_caption_size: DEFAULT_MAX_CAPTION_SIZE,
+        }
+    }
+}
+
+impl FetchOptions {
+    pub(crate) fn user_agent(&self) -> &str {
+        self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+    }
+
+    // The base URL without a trailing slash, ready for a path to be appended
+    pub(crate) fn base_url(&self) -> &str {
+        self.base_url.trim_end_matches('/')
+    }
+}
+
+// Delay before the given retry (0-based): 1s, 2s, 4s, ...
+pub fn backoff_delay(attempt: u32) -> Duration {
+    Duration::from_secs(1 << attempt.min(6))
+}
+
+// The backoff delay with "equal jitter": somewhere between half of it and all of
+// it, picked from `seed`. Batch downloads hitting the same rate limit would
+// otherwise all retry at the same moment. The same seed always gives the same delay
+pub fn jittered_backoff_delay(attempt: u32, seed: u64) -> Duration {
+    let half = backoff_delay(attempt) / 2;
+    // The top 53 bits as a fraction in [0, 1)
+    let fraction = (splitmix64(seed) >> 11) as f64 / (1u64 << 53) as f64;
+    half + half.mul_f64(fraction)
+}
+
+// SplitMix64, enough randomness to spread retries without pulling in a crate
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// A different seed for every retry: the clock plus a counter, so retries that
+// happen at the very same moment still get different delays
+pub(crate) fn jitter_seed() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    nanos ^ COUNTER.fetch_add(1, Ordering::Relaxed).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+// Longest Retry-After wait that's honoured, so a bogus header can't stall a run
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+// How long a Retry-After header asks to wait, given either as delta-seconds
+// ("120") or as an HTTP date ("Wed, 21 Oct 2015 07:28:00 GMT"). A date in the
+// past means no wait at all
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            date.duration_since(now).unwrap_or(Duration::ZERO)
+        }
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+// Rate limiting and server-side failures are worth another try, anything else
+// (a 404 in particular) will fail the same way again
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
+
+// How much of an error response's body ends up in TranscriptError::BadStatus
+const MAX_SNIPPET_LEN: usize = 200;
+
+// How many bytes of an error response are read to make that snippet; enough
+// for MAX_SNIPPET_LEN characters of any width, and the rest is never downloaded
+pub(crate) const MAX_ERROR_BODY_LEN: usize = MAX_SNIPPET_LEN * 4;
+
+// The start of a response body on one line, for error messages
+pub(crate) fn body_snippet(body: &str) -> String {
+    let body = crate::collapse_whitespace(body);
+    if body.chars().count() > MAX_SNIPPET_LEN {
+        let truncated: String = body.chars().take(MAX_SNIPPET_LEN).collect();
+        format!("{}...", truncated)
+    } else {
+        body
+    }
+}
+
+// Turn a response with a non-success status into BadStatus, keeping the start
+// of its body since YouTube usually explains the failure there
+async fn check_status(mut response: Response) -> Result<Response, TranscriptError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let mut body = Vec::new();
+    while body.len() < MAX_ERROR_BODY_LEN {
+        match response.chunk().await {
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            _ => break,
+        }
+    }
+    body.truncate(MAX_ERROR_BODY_LEN);
+    Err(TranscriptError::BadStatus {
+        code: status.as_u16(),
+        snippet: body_snippet(&String::from_utf8_lossy(&body)),
+    })
+}
+
+// Read a response body, giving up with ResponseTooLarge once it passes `limit`
+// bytes instead of buffering however much the server sends. A declared
+// Content-Length over the limit fails before anything is read
+pub(crate) async fn read_bytes(
+    mut response: Response,
+    limit: usize,
+) -> Result<Vec<u8>, TranscriptError> {
+    if response.content_length().is_some_and(|len| len > limit as u64) {
+        return Err(TranscriptError::ResponseTooLarge { limit });
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(TranscriptError::ResponseTooLarge { limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+// Same as read_bytes, as text
+pub(crate) async fn read_text(response: Response, limit: usize) -> Result<String, TranscriptError> {
+    let body = read_bytes(response, limit).await?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+// How many responses get_text_memoized holds on to before starting over
+const MAX_MEMOIZED_RESPONSES: usize = 16;
+
+// Caption tracks already downloaded, by URL, so fetching the same track again
+// (another format, --words after a transcript, a video listed twice) doesn't
+// cost another request. Clones share the responses; every TranscriptFetcher
+// has its own, a client made straight from FetchOptions starts out empty
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ResponseMemo(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+
+// A reqwest client together with the options it was built from
+pub(crate) struct HttpClient {
+    client: Client,
+    options: FetchOptions,
+    memo: ResponseMemo,
+}
+
+impl HttpClient {
+    pub(crate) fn new(options: &FetchOptions) -> Result<Self, TranscriptError> {
+        Self::with_memo(options, ResponseMemo::default())
+    }
+
+    // A client that reuses (and adds to) the responses of `memo`
+    pub(crate) fn with_memo(
+        options: &FetchOptions,
+        memo: ResponseMemo,
+    ) -> Result<Self, TranscriptError> {
+        let mut builder = Client::builder()
+            .user_agent(options.user_agent())
+            .timeout(options.timeout)
+            .redirect(redirect_policy())
+            // Ask for compressed responses and decode them, so compressed
+            // bytes never end up being parsed as text
+            .gzip(true)
+            .brotli(true)
+            .deflate(true);
+        if let Some(proxy) = &options.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        if let Some(path) = &options.cookies {
+            builder = builder.cookie_provider(Arc::new(load_cookie_jar(path)?));
+        }
+        let client = builder.build()?;
+        Ok(HttpClient {
+            client,
+            options: options.clone(),
+            memo,
+        })
+    }
+
+    pub(crate) fn options(&self) -> &FetchOptions {
+        &self.options
+    }
+
+    // GET a URL, retrying transient failures with exponential backoff
+    // A response that still has a non-success status afterwards is a BadStatus error
+    pub(crate) async fn get(&self, url: &str) -> Result<Response, TranscriptError> {
+        self.get_with_cookie(url, None).await
+    }
+
+    // GET a URL's body as text, reusing the body from an earlier request for the
+    // very same URL, see get_bytes_memoized
+    pub(crate) async fn get_text_memoized(&self, url: &str) -> Result<String, TranscriptError> {
+        let body = self.get_bytes_memoized(url).await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    // GET a URL's body, reusing the body from an earlier request for the very
+    // same URL through this client's memo. Empty bodies aren't kept, since
+    // those are worth asking for again
+    pub(crate) async fn get_bytes_memoized(&self, url: &str) -> Result<Vec<u8>, TranscriptError> {
+        if let Some(body) = self.memo.0.lock().unwrap().get(url) {
+            debug!("Reusing the response already downloaded from {}", url);
+            return Ok(body.clone());
+        }
+
+        let body = read_bytes(self.get(url).await?, self.options.max_caption_size).await?;
+        if !body.trim_ascii().is_empty() {
+            let mut memo = self.memo.0.lock().unwrap();
+            if memo.len() >= MAX_MEMOIZED_RESPONSES {
+                memo.clear();
+            }
+            memo.insert(url.to_string(), body.clone());
+        }
+        Ok(body)
+    }
+
+    // Same as get, optionally sending an extra Cookie header along
+    pub(crate) async fn get_with_cookie(
+        &self,
+        url: &str,
+        cookie: Option<&str>,
+    ) -> Result<Response, TranscriptError> {
+        self.send(|| {
+            let request = self.client.get(url);
+            match cookie {
+                Some(cookie) => request.header(reqwest::header::COOKIE, cookie),
+                None => request,
+            }
+        })
+        .await
+    }
+
+    // POST a JSON body to a URL, retried the same way as get
+    pub(crate) async fn post_json(
+        &self,
+        url: &str,
+        body: &serde_json::Value,
+    ) -> Result<Response, TranscriptError> {
+        self.send(|| self.client.post(url).json(body)).await
+    }
+
+    // Send the request `build` makes, building it anew for every retry
+    async fn send(&self, build: impl Fn() -> RequestBuilder) -> Result<Response, TranscriptError> {
+        let mut attempt = 0;
+        loop {
+            let result = build().send().await;
+            let retryable = match &result {
+                Ok(response) => is_retryable_status(response.status()),
+                Err(error) => is_retryable_error(error),
+            };
+
+            if !retryable || attempt >= self.options.retries {
+                return check_status(result?).await;
+            }
+
+            // A rate-limited response may say how long to wait; otherwise back off
+            let retry_after = result.as_ref().ok().and_then(|response| {
+                let value = response.headers().get(reqwest::header::RETRY_AFTER)?;
+                parse_retry_after(value.to_str().ok()?, SystemTime::now())
+            });
+            let delay = retry_after.unwrap_or_else(|| jittered_backoff_delay(attempt, jitter_seed()));
+            let reason = match &result {
+                Ok(response) => response.status().to_string(),
+                Err(error) => error.to_string(),
+            };
+            warn!("Request failed ({}), retrying in {:.1}s...", reason, delay.as_secs_f64());
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+
+    fn options_for(server: &MockServer) -> FetchOptions {
+        FetchOptions {
+            base_url: server.url().to_string(),
+            ..FetchOptions::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_until_success() {
+        let server = MockServer::start();
+        // Retry-After: 0 keeps the test from sitting through the backoff
+        let unavailable = MockResponse::status(503, "try later").header("Retry-After", "0");
+        server.mock("/page", unavailable.clone());
+        server.mock("/page", unavailable);
+        server.mock("/page", MockResponse::ok("finally"));
+
+        let client = HttpClient::new(&options_for(&server)).unwrap();
+        let response = client.get(&format!("{}/page", server.url())).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "finally");
+        assert_eq!(server.hits("/page"), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_configured_retries() {
+        let server = MockServer::start();
+        server.mock("/page", MockResponse::status(500, "broken").header("Retry-After", "0"));
+
+        let options = FetchOptions {
+            retries: 2,
+            ..options_for(&server)
+        };
+        let client = HttpClient::new(&options).unwrap();
+        let error = client.get(&format!("{}/page", server.url())).await.unwrap_err();
+        assert!(matches!(error, TranscriptError::BadStatus { code: 500, .. }));
+        assert_eq!(server.hits("/page"), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_not_found() {
+        let server = MockServer::start();
+        server.mock("/page", MockResponse::status(404, "gone"));
+
+        let client = HttpClient::new(&options_for(&server)).unwrap();
+        let error = client.get(&format!("{}/page", server.url())).await.unwrap_err();
+        assert!(matches!(error, TranscriptError::BadStatus { code: 404, .. }));
+        assert_eq!(server.hits("/page"), 1);
+    }
+
+    #[tokio::test]
+    async fn a_slow_server_times_out() {
+        let server = MockServer::start();
+        server.mock("/slow", MockResponse::ok("late").delay(Duration::from_secs(2)));
+
+        let options = FetchOptions {
+            timeout: Duration::from_millis(200),
+            retries: 0,
+            ..options_for(&server)
+        };
+        let client = HttpClient::new(&options).unwrap();
+        let error = client.get(&format!("{}/slow", server.url())).await.unwrap_err();
+        assert!(matches!(error, TranscriptError::Timeout), "{:?}", error);
+    }
+
+    #[test]
+    fn builds_with_http_and_socks_proxies() {
+        for proxy in ["http://proxy.example:8080", "socks5://127.0.0.1:1080"] {
+            let options = FetchOptions {
+                proxy: Some(proxy.to_string()),
+                ..FetchOptions::default()
+            };
+            assert!(HttpClient::new(&options).is_ok(), "{}", proxy);
+        }
+    }
+
+    #[test]
+    fn rejects_a_malformed_proxy() {
+        let options = FetchOptions {
+            proxy: Some("not a url".to_string()),
+            ..FetchOptions::default()
+        };
+        assert!(HttpClient::new(&options).is_err());
+    }
+
+    #[tokio::test]
+    async fn sends_the_configured_user_agent() {
+        let server = MockServer::start();
+        server.mock("/page", MockResponse::ok("hi"));
+        let url = format!("{}/page", server.url());
+
+        HttpClient::new(&options_for(&server)).unwrap().get(&url).await.unwrap();
+        let custom = FetchOptions {
+            user_agent: Some("MyTool/1.0".to_string()),
+            ..options_for(&server)
+        };
+        HttpClient::new(&custom).unwrap().get(&url).await.unwrap();
+
+        let agents: Vec<Option<String>> =
+            server.requests().iter().map(|r| r.header("user-agent").map(str::to_string)).collect();
+        assert_eq!(
+            agents,
+            [Some(DEFAULT_USER_AGENT.to_string()), Some("MyTool/1.0".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn waits_as_long_as_retry_after_asks() {
+        let server = MockServer::start();
+        server.mock("/page", MockResponse::status(429, "slow down").header("Retry-After", "2"));
+        server.mock("/page", MockResponse::ok("ok"));
+
+        let client = HttpClient::new(&options_for(&server)).unwrap();
+        let started = std::time::Instant::now();
+        client.get(&format!("{}/page", server.url())).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(2));
+        assert_eq!(server.hits("/page"), 2);
+    }
+
+    #[test]
+    fn retry_after_takes_seconds_or_a_date() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        // A date that has already passed means trying again straight away
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-5", now), None);
+    }
+
+    #[test]
+    fn retry_after_is_capped() {
+        assert_eq!(parse_retry_after("86400", SystemTime::now()), Some(MAX_RETRY_AFTER));
+    }
+
+    #[test]
+    fn jittered_delays_fall_between_half_and_all_of_the_backoff() {
+        for attempt in 0..8 {
+            let full = backoff_delay(attempt);
+            let delays: Vec<Duration> = (0..200).map(|seed| jittered_backoff_delay(attempt, seed)).collect();
+            assert!(delays.iter().all(|delay| *delay >= full / 2 && *delay <= full), "attempt {}", attempt);
+            // Different seeds spread the retries out
+            let distinct: std::collections::HashSet<Duration> = delays.iter().copied().collect();
+            assert!(distinct.len() > 100, "attempt {}: {} distinct delays", attempt, distinct.len());
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_delay() {
+        assert_eq!(jittered_backoff_delay(3, 42), jittered_backoff_delay(3, 42));
+        assert_ne!(jittered_backoff_delay(3, 42), jittered_backoff_delay(3, 43));
+        assert_ne!(jitter_seed(), jitter_seed());
+    }
+
+    #[tokio::test]
+    async fn only_the_start_of_a_huge_error_body_is_read() {
+        let server = MockServer::start();
+        let body = "x".repeat(16 * 1024 * 1024);
+        server.mock("/sized", MockResponse::status(404, body.clone()));
+        server.mock("/chunked", MockResponse::status(404, body).chunked());
+
+        let client = HttpClient::new(&options_for(&server)).unwrap();
+        for route in ["/sized", "/chunked"] {
+            let error = client.get(&format!("{}{}", server.url(), route)).await.unwrap_err();
+            let TranscriptError::BadStatus { code, snippet } = error else {
+                panic!("expected BadStatus, got {:?}", error);
+            };
+            assert_eq!(code, 404);
+            assert_eq!(snippet, format!("{}...", "x".repeat(MAX_SNIPPET_LEN)));
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// YouTube's internal "Innertube" player API, which answers a JSON POST with the
+// same player response the watch page embeds, captions included. It doesn't
+// depend on the page's markup, so it keeps working when scraping breaks
+use crate::http::{read_text, HttpClient};
+use crate::playability::check_playability;
+use crate::TranscriptError;
+use log::info;
+use serde_json::{json, Value};
+
+// Appended to FetchOptions::base_url
+pub(crate) const INNERTUBE_PLAYER_PATH: &str = "/youtubei/v1/player";
+
+// The web client the watch page itself identifies as
+const INNERTUBE_CLIENT_NAME: &str = "WEB";
+const INNERTUBE_CLIENT_VERSION: &str = "2.20240101.00.00";
+
+// The body of a player request for one video
+pub(crate) fn player_request_body(video_id: &str) -> Value {
+    json!({
+        "context": {
+            "client": {
+                "clientName": INNERTUBE_CLIENT_NAME,
+                "clientVersion": INNERTUBE_CLIENT_VERSION,
+                "hl": "en",
+            }
+        },
+        "videoId": video_id,
+    })
+}
+
+// Parse the player API's answer, checking the video can be watched like
+// crate::parse_player_response does for the watch page
+pub fn parse_innertube_response(body: &str) -> Result<Value, TranscriptError> {
+    let parsed: Value = serde_json::from_str(body)?;
+    if !parsed.is_object() {
+        return Err(TranscriptError::PlayerDataNotFound);
+    }
+    check_playability(&parsed)?;
+    Ok(parsed)
+}
+
+// Ask the player API for a video's player response
+pub(crate) async fn fetch_innertube_player(
+    client: &HttpClient,
+    video_id: &str,
+) -> Result<Value, TranscriptError> {
+    info!("Fetching player data from the player API...");
+    let url = format!("{}{}", client.options().base_url(), INNERTUBE_PLAYER_PATH);
+    let response = client.post_json(&url, &player_request_body(video_id)).await?;
+    let body = read_text(response, client.options().max_page_size).await?;
+    parse_innertube_response(&body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caption_tracks;
+    use crate::mock_server::{json3_body, player_response, MockResponse, MockServer};
+    use crate::FetchOptions;
+
+    #[test]
+    fn parses_the_captions_of_a_captured_response() {
+        let parsed = parse_innertube_response(include_str!("../tests/fixtures/innertube_player.json"))
+            .unwrap();
+        let tracks = caption_tracks(&parsed).unwrap();
+        let codes: Vec<&str> = tracks.iter().filter_map(|t| t["languageCode"].as_str()).collect();
+        assert_eq!(codes, ["en", "fr"]);
+        assert!(tracks[0]["baseUrl"].as_str().unwrap().contains("lang=en&kind=asr"));
+        assert_eq!(parsed["videoDetails"]["author"], "Rick Astley");
+    }
+
+    #[test]
+    fn an_unplayable_or_odd_response_is_an_error() {
+        let private = include_str!("../tests/fixtures/player_private.json");
+        assert!(matches!(parse_innertube_response(private), Err(TranscriptError::Private)));
+        assert!(matches!(parse_innertube_response("[]"), Err(TranscriptError::PlayerDataNotFound)));
+        assert!(parse_innertube_response("<html>").is_err());
+    }
+
+    #[test]
+    fn the_request_names_the_web_client_and_the_video() {
+        let body = player_request_body("dQw4w9WgXcQ");
+        assert_eq!(body["videoId"], "dQw4w9WgXcQ");
+        assert_eq!(body["context"]["client"]["clientName"], "WEB");
+    }
+
+    #[tokio::test]
+    async fn use_api_posts_to_the_player_endpoint_instead_of_scraping() {
+        let server = MockServer::start();
+        let player = player_response(server.url(), "dQw4w9WgXcQ", &["en"]);
+        server.mock(INNERTUBE_PLAYER_PATH, MockResponse::ok(player.to_string()));
+        server.mock(
+            "/api/timedtext?v=dQw4w9WgXcQ&lang=en&fmt=json3",
+            MockResponse::ok(json3_body(&[(0, 1000, "from the api")])),
+        );
+        let options = FetchOptions {
+            base_url: server.url().to_string(),
+            use_api: true,
+            ..FetchOptions::default()
+        };
+
+        let (metadata, transcript) = crate::fetch_video("dQw4w9WgXcQ", None, &options).await.unwrap();
+        assert_eq!(transcript[0].text, "from the api");
+        assert_eq!(metadata.title.as_deref(), Some("Mock video"));
+        assert_eq!(server.hits("/watch"), 0);
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "POST");
+        let sent: Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(sent, player_request_body("dQw4w9WgXcQ"));
+    }
+}
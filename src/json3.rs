@@ -0,0 +1,421 @@
+// Parser for YouTube's json3 caption format, requested by adding fmt=json3 to a
+// caption track's baseUrl. It comes as plain JSON, so there's no markup to untangle
+use crate::{
+    collapse_whitespace, format_seconds_precise, with_query_param, TranscriptError, TranscriptItem,
+};
+use serde::{Deserialize, Serialize};
+
+// When a single word of a caption is spoken, in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WordTiming {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+impl WordTiming {
+    // The word's start as [MM:SS.mmm]
+    pub fn format_time(&self) -> String {
+        format_seconds_precise(self.start)
+    }
+}
+
+#[derive(Deserialize)]
+struct Json3Transcript {
+    #[serde(default)]
+    events: Vec<Json3Event>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Json3Event {
+    #[serde(default)]
+    t_start_ms: u64,
+    #[serde(default)]
+    d_duration_ms: u64,
+    // Events without segments only describe caption windows and carry no text
+    #[serde(default)]
+    segs: Vec<Json3Segment>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Json3Segment {
+    #[serde(default)]
+    utf8: String,
+    // Offset of the segment from the start of its event
+    #[serde(default)]
+    t_offset_ms: u64,
+    // How sure the speech recognizer was about the segment, 0-255
+    // Only auto-generated tracks have it, and not on every segment
+    #[serde(default)]
+    ac_asr_conf: Option<u8>,
+}
+
+impl Json3Segment {
+    // Whether the segment clears the confidence threshold (0.0-1.0); segments
+    // without a confidence value always do
+    fn is_confident(&self, min_confidence: Option<f64>) -> bool {
+        match (self.ac_asr_conf, min_confidence) {
+            (Some(conf), Some(min)) => f64::from(conf) / 255.0 >= min,
+            _ => true,
+        }
+    }
+}
+
+// The URL of a caption track in json3 format instead of the default XML
+pub(crate) fn json3_url(base_url: &str) -> String {
+    with_query_param(base_url, "fmt", "json3")
+}
+
+fn parse_document(body: &str) -> Result<Json3Transcript, TranscriptError> {
+    serde_json::from_str(body).map_err(|e| TranscriptError::Parse(format!("invalid json3 captions: {}", e)))
+}
+
+fn parse_event(bytes: &[u8]) -> Result<Json3Event, TranscriptError> {
+    serde_json::from_slice(bytes).map_err(|e| TranscriptError::Parse(format!("invalid json3 captions: {}", e)))
+}
+
+// Parse a json3 caption document into transcript lines
+// The segments of an event are joined into one line; events that only hold
+// whitespace (YouTube uses them to append line breaks) are skipped
+pub fn parse_json3_transcript(body: &str) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    parse_json3_transcript_with(body, None)
+}
+
+// Same as parse_json3_transcript, dropping speech recognition segments whose
+// confidence is below min_confidence (0.0-1.0). Tracks without confidence
+// values come back unchanged
+pub fn parse_json3_transcript_with(
+    body: &str,
+    min_confidence: Option<f64>,
+) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    let document = parse_document(body)?;
+
+    Ok(document
+        .events
+        .iter()
+        .filter_map(|event| event_line(event, min_confidence))
+        .enumerate()
+        .map(|(index, item)| TranscriptItem { index, ..item })
+        .collect())
+}
+
+// The transcript line of one event, None when it holds no text
+// The index is left at 0 for the caller to number the lines
+fn event_line(event: &Json3Event, min_confidence: Option<f64>) -> Option<TranscriptItem> {
+    let text: String = event
+        .segs
+        .iter()
+        .filter(|seg| seg.is_confident(min_confidence))
+        .map(|seg| seg.utf8.as_str())
+        .collect();
+    let text = collapse_whitespace(&text);
+    (!text.is_empty()).then(|| TranscriptItem {
+        text,
+        start: event.t_start_ms as f64 / 1000.0,
+        duration: event.d_duration_ms as f64 / 1000.0,
+        index: 0,
+    })
+}
+
+// Picks the events out of a json3 document while it's still downloading, so
+// each line can be handed over as soon as its event is complete. Only the
+// event being read is kept in memory, not the whole body
+// The lines are the same as parse_json3_transcript_with gives for the full body
+pub(crate) struct Json3Splitter {
+    min_confidence: Option<f64>,
+    // Bytes not yet handed over, starting at the event or key being read
+    buffer: Vec<u8>,
+    // How much of the buffer has been looked at
+    scanned: usize,
+    // Nesting depth of objects and arrays, 1 inside the top-level object
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    // Where in the buffer the current top-level string or event object starts
+    start: usize,
+    // The last string seen directly inside the top-level object; the key of
+    // the value that follows when an array opens
+    last_key: Vec<u8>,
+    in_events: bool,
+    lines: usize,
+}
+
+impl Json3Splitter {
+    pub(crate) fn new(min_confidence: Option<f64>) -> Self {
+        Json3Splitter {
+            min_confidence,
+            buffer: Vec::new(),
+            scanned: 0,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            start: 0,
+            last_key: Vec::new(),
+            in_events: false,
+            lines: 0,
+        }
+    }
+
+    // Take the next piece of the body, returning the lines of the events it completes
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Result<Vec<TranscriptItem>, TranscriptError> {
+        self.buffer.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+
+        for i in self.scanned..self.buffer.len() {
+            let byte = self.buffer[i];
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                    if self.depth == 1 {
+                        self.last_key = self.buffer[self.start + 1..i].to_vec();
+                    }
+                }
+                continue;
+            }
+
+            match byte {
+                b'"' => {
+                    self.in_string = true;
+                    if self.depth == 1 {
+                        self.start = i;
+                    }
+                }
+                b'{' | b'[' => {
+                    self.depth += 1;
+                    if self.depth == 2 && byte == b'[' && self.last_key == b"events" {
+                        self.in_events = true;
+                    } else if self.in_events && self.depth == 3 {
+                        self.start = i;
+                    }
+                }
+                b'}' | b']' => {
+                    if self.in_events && self.depth == 3 {
+                        let event = parse_event(&self.buffer[self.start..=i])?;
+                        if let Some(line) = event_line(&event, self.min_confidence) {
+                            lines.push(TranscriptItem {
+                                index: self.lines,
+                                ..line
+                            });
+                            self.lines += 1;
+                        }
+                    } else if self.in_events && self.depth == 2 {
+                        self.in_events = false;
+                    }
+                    self.depth = self.depth.saturating_sub(1);
+                }
+                _ => {}
+            }
+        }
+
+        // Drop what's been dealt with, keeping an unfinished event or key
+        let keep_from = if (self.in_events && self.depth >= 3) || (self.in_string && self.depth == 1) {
+            self.start
+        } else {
+            self.buffer.len()
+        };
+        self.buffer.drain(..keep_from);
+        self.start -= keep_from.min(self.start);
+        self.scanned = self.buffer.len();
+        Ok(lines)
+    }
+
+    // Check that the document ended where it should once the body is complete
+    pub(crate) fn finish(&self) -> Result<(), TranscriptError> {
+        if self.depth > 0 || self.in_string {
+            let message = "invalid json3 captions: the document is cut short";
+            return Err(TranscriptError::Parse(message.to_string()));
+        }
+        if self.lines == 0 {
+            return Err(TranscriptError::EmptyTranscript);
+        }
+        Ok(())
+    }
+}
+
+// Parse the per-word timings of a json3 caption document
+// Each segment of an event is one word starting at the event's start plus the
+// segment's offset; it lasts until the next segment starts, and the last one
+// until the event ends
+pub fn parse_json3_words(body: &str) -> Result<Vec<WordTiming>, TranscriptError> {
+    parse_json3_words_with(body, None)
+}
+
+// Same as parse_json3_words, leaving out words whose speech recognition
+// confidence is below min_confidence (0.0-1.0)
+pub fn parse_json3_words_with(
+    body: &str,
+    min_confidence: Option<f64>,
+) -> Result<Vec<WordTiming>, TranscriptError> {
+    let document = parse_document(body)?;
+    let mut words = Vec::new();
+
+    for event in &document.events {
+        let event_end = event.t_start_ms + event.d_duration_ms;
+        for (i, seg) in event.segs.iter().enumerate() {
+            let word = seg.utf8.trim();
+            if word.is_empty() || !seg.is_confident(min_confidence) {
+                continue;
+            }
+            let start_ms = event.t_start_ms + seg.t_offset_ms;
+            let end_ms = event
+                .segs
+                .get(i + 1)
+                .map_or(event_end, |next| event.t_start_ms + next.t_offset_ms)
+                .max(start_ms);
+            words.push(WordTiming {
+                word: word.to_string(),
+                start: start_ms as f64 / 1000.0,
+                end: end_ms as f64 / 1000.0,
+            });
+        }
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/captions_asr.json3");
+
+    #[test]
+    fn parses_a_captured_json3_track() {
+        let transcript = parse_json3_transcript(FIXTURE).unwrap();
+        let lines: Vec<(&str, f64, f64, usize)> = transcript
+            .iter()
+            .map(|item| (item.text.as_str(), item.start, item.duration, item.index))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ("we're no strangers", 0.12, 3.0, 0),
+                ("to love", 3.12, 2.88, 1),
+                ("[Music]", 6.0, 2.0, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_json3_url_asks_for_json3() {
+        assert_eq!(
+            json3_url("https://www.youtube.com/api/timedtext?v=x&lang=en"),
+            "https://www.youtube.com/api/timedtext?v=x&lang=en&fmt=json3"
+        );
+    }
+
+    #[test]
+    fn broken_json3_is_a_parse_error() {
+        assert!(matches!(
+            parse_json3_transcript("{\"events\": [").unwrap_err(),
+            TranscriptError::Parse(_)
+        ));
+    }
+
+    #[test]
+    fn splitting_byte_by_byte_gives_the_same_lines() {
+        for min_confidence in [None, Some(0.5)] {
+            let mut splitter = Json3Splitter::new(min_confidence);
+            let mut lines = Vec::new();
+            for byte in FIXTURE.as_bytes() {
+                lines.extend(splitter.push(&[*byte]).unwrap());
+            }
+            splitter.finish().unwrap();
+            assert_eq!(lines, parse_json3_transcript_with(FIXTURE, min_confidence).unwrap());
+        }
+    }
+
+    #[test]
+    fn splitting_ignores_brackets_and_quotes_inside_strings() {
+        let body = r#"{"note": "events [{", "events": [{"tStartMs": 0, "dDurationMs": 10, "segs": [{"utf8": "a \"[b]\" {c}"}]}]}"#;
+        let mut splitter = Json3Splitter::new(None);
+        let (head, tail) = body.split_at(body.len() / 2);
+        let mut lines = splitter.push(head.as_bytes()).unwrap();
+        lines.extend(splitter.push(tail.as_bytes()).unwrap());
+        splitter.finish().unwrap();
+        assert_eq!(lines, parse_json3_transcript(body).unwrap());
+        assert_eq!(lines[0].text, "a \"[b]\" {c}");
+    }
+
+    #[test]
+    fn splitting_only_keeps_the_unfinished_event() {
+        let mut splitter = Json3Splitter::new(None);
+        let events: Vec<String> = (0..1000)
+            .map(|i| {
+                let segs = format!(r#"[{{"utf8": "line {}"}}]"#, i);
+                format!(r#"{{"tStartMs": {}, "dDurationMs": 1000, "segs": {}}}"#, i * 1000, segs)
+            })
+            .collect();
+        splitter.push(br#"{"wireMagic": "pb3", "events": ["#).unwrap();
+        let mut count = 0;
+        for event in &events {
+            count += splitter.push(event.as_bytes()).unwrap().len();
+            splitter.push(b",").unwrap();
+            assert!(splitter.buffer.len() <= event.len());
+        }
+        assert_eq!(count, 1000);
+    }
+
+    #[test]
+    fn a_cut_short_document_is_an_error() {
+        let mut splitter = Json3Splitter::new(None);
+        assert_eq!(splitter.push(&FIXTURE.as_bytes()[..FIXTURE.len() - 4]).unwrap().len(), 3);
+        assert!(matches!(splitter.finish(), Err(TranscriptError::Parse(_))));
+    }
+
+    #[test]
+    fn words_are_timed_from_their_segment_offsets() {
+        let words: Vec<(String, f64, f64)> = parse_json3_words(FIXTURE)
+            .unwrap()
+            .into_iter()
+            .map(|word| (word.word, word.start, word.end))
+            .collect();
+        let expected = [
+            ("we're", 0.12, 0.6),
+            ("no", 0.6, 1.08),
+            // The last segment lasts until its event ends
+            ("strangers", 1.08, 3.12),
+            ("to", 3.12, 3.72),
+            ("love", 3.72, 6.0),
+            ("[Music]", 6.0, 8.0),
+        ];
+        assert_eq!(words.len(), expected.len());
+        for ((word, start, end), (expected_word, expected_start, expected_end)) in
+            words.iter().zip(expected)
+        {
+            assert_eq!(word, expected_word);
+            assert!((start - expected_start).abs() < 1e-9 && (end - expected_end).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn word_times_are_shown_to_the_millisecond() {
+        let words = parse_json3_words(FIXTURE).unwrap();
+        assert_eq!(words[1].format_time(), "[00:00.600]");
+    }
+
+    #[test]
+    fn low_confidence_segments_are_dropped_and_the_rest_kept() {
+        let texts = |min_confidence| -> Vec<String> {
+            parse_json3_transcript_with(FIXTURE, min_confidence)
+                .unwrap()
+                .into_iter()
+                .map(|item| item.text)
+                .collect()
+        };
+        // "strangers" has a confidence of 30/255; [Music] has none, so it's never dropped
+        assert_eq!(texts(Some(0.5)), ["we're no", "to love", "[Music]"]);
+        assert_eq!(texts(Some(0.0)), texts(None));
+        assert_eq!(texts(Some(1.0)), ["love", "[Music]"]);
+
+        let words = parse_json3_words_with(FIXTURE, Some(0.5)).unwrap();
+        assert!(words.iter().all(|word| word.word != "strangers"));
+        assert_eq!(words.len(), 5);
+    }
+}
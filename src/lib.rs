@@ -0,0 +1,2282 @@
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use log::{debug, info, warn};
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+mod analysis;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod cache;
+mod captions;
+mod channel;
+mod chapters;
+mod cookies;
+mod error;
+mod fetcher;
+mod format;
+mod http;
+mod innertube;
+mod json3;
+mod metadata;
+#[cfg(test)]
+mod mock_server;
+mod playability;
+mod playlist;
+mod subtitles;
+mod transform;
+mod xml;
+
+pub use analysis::{find_gaps, search, transcript_stats, TranscriptStats};
+pub use cache::{read_cache, write_cache};
+pub use captions::{caption_tracks, parse_caption_tracks, select_caption_track, CaptionTrackInfo};
+pub use chapters::{parse_chapter_markers, parse_description_chapters, Chapter};
+pub use cookies::{load_cookie_jar, parse_netscape_cookies, NetscapeCookie};
+pub use error::TranscriptError;
+pub use fetcher::TranscriptFetcher;
+pub use format::{
+    to_csv, to_json, to_json_with_metadata, to_jsonl, to_markdown, to_plain_text, to_precise_text,
+    to_srt, to_text_with_durations, to_vtt, wrap_lines,
+};
+pub use http::{
+    backoff_delay, jittered_backoff_delay, parse_retry_after, FetchOptions, DEFAULT_BASE_URL,
+    DEFAULT_MAX_CAPTION_SIZE, DEFAULT_MAX_PAGE_SIZE, DEFAULT_USER_AGENT,
+};
+pub use innertube::parse_innertube_response;
+pub use json3::{
+    parse_json3_transcript, parse_json3_transcript_with, parse_json3_words, parse_json3_words_with,
+    WordTiming,
+};
+pub use channel::{
+    build_channel_videos_url, fetch_channel_video_ids, parse_channel_handle, parse_channel_video_ids,
+};
+pub use metadata::{parse_video_metadata, VideoMetadata};
+pub use playlist::{
+    extract_initial_data, fetch_playlist_video_ids, parse_playlist_id, parse_playlist_video_ids,
+};
+pub use subtitles::{parse_srt, parse_vtt};
+pub use transform::{
+    ascii_punctuation, dedupe_rolling, filter_range, mark_speaker_turns, merge_into_sentences,
+    normalize_punctuation, speaker_label, starts_speaker_turn, strip_annotations,
+    ANNOTATION_KEYWORDS, MAX_SENTENCE_SECS, SPEAKER_MARKER,
+};
+pub use xml::{decode_entities, parse_transcript_xml, parse_transcript_xml_with_counts, SegmentCounts};
+
+use http::{jitter_seed, read_text, HttpClient};
+use innertube::fetch_innertube_player;
+use json3::{json3_url, Json3Splitter};
+use playability::{check_playability, is_live_now};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptItem {
+    pub text: String,
+    pub start: f64,
+    pub duration: f64,
+    // Position of the entry in the caption track as parsed, counting from 0
+    // Filtering keeps it, so it always points back at the original entry; an
+    // entry merged from several keeps the index of the first
+    #[serde(default)]
+    pub index: usize,
+}
+
+impl TranscriptItem {
+    // This method formats the timestamp of a transcript item into a readable string
+    // It takes the start time in seconds and converts it to [MM:SS] format,
+    // switching to [HH:MM:SS] once the video runs past the one hour mark
+    // For example: 
+    // - If start time is 65.0 seconds, returns "[01:05]"
+    // - If start time is 125.5 seconds, returns "[02:05]"
+    // - If start time is 3725.0 seconds, returns "[01:02:05]"
+    pub fn format_time(&self) -> String {
+        format_seconds(self.start)
+    }
+
+    // When the entry stops being shown, in seconds
+    pub fn end(&self) -> f64 {
+        self.start + self.duration
+    }
+
+    // Same as format_time, but keeps the milliseconds: [MM:SS.mmm] or [HH:MM:SS.mmm]
+    pub fn format_time_precise(&self) -> String {
+        format_seconds_precise(self.start)
+    }
+}
+
+// The [MM:SS] / [HH:MM:SS] rendering behind TranscriptItem::format_time
+pub fn format_seconds(seconds: f64) -> String {
+    let total_secs = seconds.max(0.0).floor() as u64; // Drop the fractional part
+    let hours = total_secs / 3600; // Whole hours
+    let mins = (total_secs % 3600) / 60; // Remaining minutes
+    let secs = total_secs % 60; // Remaining seconds
+    if hours > 0 {
+        format!("[{:02}:{:02}:{:02}]", hours, mins, secs) // Format as [HH:MM:SS]
+    } else {
+        format!("[{:02}:{:02}]", mins, secs) // Format as [MM:SS]
+    }
+}
+
+// How the timestamps of normalized lines are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LabelFormat {
+    // [MM:SS], or [HH:MM:SS] past the first hour, the same as format_time
+    #[default]
+    Minutes,
+    // Always [H:MM:SS], so every line of a long video lines up
+    Hours,
+}
+
+impl LabelFormat {
+    pub fn label(self, seconds: f64) -> String {
+        match self {
+            LabelFormat::Minutes => format_seconds(seconds),
+            LabelFormat::Hours => {
+                let total_secs = seconds.max(0.0).floor() as u64;
+                format!("[{}:{:02}:{:02}]", total_secs / 3600, (total_secs % 3600) / 60, total_secs % 60)
+            }
+        }
+    }
+}
+
+// Render seconds as [MM:SS.mmm], or [HH:MM:SS.mmm] past the first hour
+// The time is rounded to the nearest millisecond first, so 59.9995 becomes "[01:00.000]"
+pub(crate) fn format_seconds_precise(seconds: f64) -> String {
+    let total_ms = (seconds.max(0.0) * 1000.0).round() as u64;
+    let hours = total_ms / 3_600_000;
+    let mins = (total_ms % 3_600_000) / 60_000;
+    let secs = (total_ms % 60_000) / 1000;
+    let millis = total_ms % 1000;
+    if hours > 0 {
+        format!("[{:02}:{:02}:{:02}.{:03}]", hours, mins, secs, millis)
+    } else {
+        format!("[{:02}:{:02}.{:03}]", mins, secs, millis)
+    }
+}
+
+// Length of the JSON object at the start of `text`, found by balancing braces
+// (ignoring any that appear inside string literals) rather than looking for a
+// terminator, since what follows the object varies from page to page
+fn json_object_len(text: &str) -> Option<usize> {
+    if !text.starts_with('{') {
+        return None;
+    }
+
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+// Find a JavaScript assignment like `marker{...};` in the page and return
+// the JSON object that follows the marker
+fn extract_assigned_json<'a>(html: &'a str, start_marker: &str) -> Option<&'a str> {
+    let start_idx = html.find(start_marker)?;
+    let sub_str = html[start_idx + start_marker.len()..].trim_start();
+    let len = json_object_len(sub_str)?;
+    Some(&sub_str[..len])
+}
+
+// The ways watch pages have been seen assigning the player response, tried in order
+const PLAYER_RESPONSE_MARKERS: &[&str] = &[
+    "var ytInitialPlayerResponse = ",
+    "window[\"ytInitialPlayerResponse\"] = ",
+    "ytInitialPlayerResponse = ",
+    "ytInitialPlayerResponse=",
+];
+
+pub fn extract_json(html: &str) -> Option<&str> {
+    PLAYER_RESPONSE_MARKERS
+        .iter()
+        .find_map(|marker| extract_assigned_json(html, marker))
+}
+
+pub fn is_valid_video_id(id: &str) -> bool {
+    id.len() == 11 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Fail fast on something that can't be a video ID, before any request is made
+pub fn validate_video_id(id: &str) -> Result<(), TranscriptError> {
+    if is_valid_video_id(id) {
+        Ok(())
+    } else {
+        Err(TranscriptError::InvalidVideoId(id.to_string()))
+    }
+}
+
+// Pull the video ID out of the common YouTube link shapes:
+// - https://www.youtube.com/watch?v=ID
+// - https://youtu.be/ID
+// - https://www.youtube.com/shorts/ID
+// - https://www.youtube.com/embed/ID
+// Anything else is accepted only if it already looks like a bare 11-character ID
+pub fn parse_video_id(input: &str) -> Option<String> {
+    parse_video_link(input).map(|(id, _)| id)
+}
+
+// Same as parse_video_id, also returning the start offset in seconds that a
+// link's t parameter asks for (https://youtu.be/ID?t=90), if it has one
+pub fn parse_video_link(input: &str) -> Option<(String, Option<f64>)> {
+    let input = input.trim();
+    let id = parse_link_id(input)?;
+    let start = input
+        .split(['?', '&', '#'])
+        .skip(1)
+        .find_map(|param| param.strip_prefix("t="))
+        .and_then(parse_start_offset);
+    Some((id, start))
+}
+
+// Parse the value of a t parameter: plain seconds ("90"), with a unit ("90s"),
+// or in hours, minutes and seconds ("1m30s", "1h2m3s")
+pub fn parse_start_offset(value: &str) -> Option<f64> {
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs as f64);
+    }
+
+    let mut total = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let multiplier = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total += number.parse::<u64>().ok()? * multiplier;
+        number.clear();
+    }
+    // Every number needs a unit once units are used
+    (number.is_empty() && !value.is_empty()).then_some(total as f64)
+}
+
+fn parse_link_id(input: &str) -> Option<String> {
+    let markers = ["?v=", "&v=", "youtu.be/", "/shorts/", "/embed/"];
+
+    for marker in markers {
+        if let Some(idx) = input.find(marker) {
+            let id: String = input[idx + marker.len()..]
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+                .collect();
+            return is_valid_video_id(&id).then_some(id);
+        }
+    }
+
+    is_valid_video_id(input).then(|| input.to_string())
+}
+
+// Longest title (in characters) kept when it's used as a file name
+const MAX_FILENAME_TITLE_LEN: usize = 80;
+
+// Turn a video title into something safe to use as a file name
+// Path separators, colons and the other characters Windows rejects are dropped
+// along with control characters, whitespace runs collapse to a single space and
+// the result is cut down to a sane length. Emoji and other unicode are kept
+pub fn sanitize_filename(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|'))
+        .collect();
+    let collapsed = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let truncated: String = collapsed.chars().take(MAX_FILENAME_TITLE_LEN).collect();
+    truncated.trim().trim_end_matches('.').to_string()
+}
+
+pub fn build_youtube_url(video_id: &str) -> String {
+    build_youtube_url_with(DEFAULT_BASE_URL, video_id)
+}
+
+// The watch page URL on another host, see FetchOptions::base_url
+pub fn build_youtube_url_with(base_url: &str, video_id: &str) -> String {
+    format!("{}/watch?v={}", base_url.trim_end_matches('/'), video_id)
+}
+
+// Cookies that tell YouTube the (implied) consent form has been accepted
+pub(crate) const CONSENT_COOKIES: &str = "CONSENT=YES+cb; SOCS=CAI";
+
+// From European IPs the watch page can be replaced by YouTube's cookie consent
+// interstitial, which has no player data in it
+pub fn is_consent_page(html: &str) -> bool {
+    html.contains("consent.youtube.com")
+        || (html.contains("CONSENT") && !html.contains("ytInitialPlayerResponse"))
+}
+
+// Whether a request was redirected to the consent interstitial, which lives on
+// its own host (consent.youtube.com or consent.google.com)
+pub(crate) fn is_consent_url(url: &reqwest::Url) -> bool {
+    url.host_str().is_some_and(|host| host.starts_with("consent."))
+}
+
+// Make sure a watch page request really ended up on a watch page. Redirects can
+// land on the consent host, a Google "sorry" page or a generic error page, none
+// of which has player data in it, so fail clearly instead of parsing those
+// Pages served from the configured base URL's host count as YouTube's too
+pub(crate) fn check_watch_url(url: &reqwest::Url, base_url: &str) -> Result<(), TranscriptError> {
+    let on_youtube = url
+        .host_str()
+        .is_some_and(|host| host == "youtube.com" || host.ends_with(".youtube.com"));
+    let on_base = reqwest::Url::parse(base_url)
+        .is_ok_and(|base| base.host_str() == url.host_str() && base.port() == url.port());
+    if (on_youtube || on_base) && !is_consent_url(url) && url.path() == "/watch" {
+        Ok(())
+    } else {
+        Err(TranscriptError::UnexpectedRedirect { url: url.to_string() })
+    }
+}
+
+// Download the watch page and pull out the ytInitialPlayerResponse JSON,
+// which holds both the caption tracks and the video details
+async fn fetch_player_response(
+    client: &HttpClient,
+    video_id: &str,
+) -> Result<serde_json::Value, TranscriptError> {
+    fetch_player_data(client, video_id).await.map(|(_, parsed)| parsed)
+}
+
+// The player response together with the watch page it came from, if any
+// With options.use_api the player API is asked directly and there's no page;
+// otherwise the API is only the fallback for a page without player data
+async fn fetch_player_data(
+    client: &HttpClient,
+    video_id: &str,
+) -> Result<(Option<String>, serde_json::Value), TranscriptError> {
+    if client.options().use_api {
+        validate_video_id(video_id)?;
+        return Ok((None, fetch_innertube_player(client, video_id).await?));
+    }
+
+    let html = fetch_watch_page(client, video_id).await?;
+    match parse_player_response(&html) {
+        Ok(parsed) => Ok((Some(html), parsed)),
+        Err(TranscriptError::PlayerDataNotFound) => {
+            warn!("No player data in the watch page, trying the player API...");
+            Ok((Some(html), fetch_innertube_player(client, video_id).await?))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Fetch the HTML of a video's watch page
+async fn fetch_watch_page(client: &HttpClient, video_id: &str) -> Result<String, TranscriptError> {
+    validate_video_id(video_id)?;
+    let base_url = client.options().base_url();
+    let url = build_youtube_url_with(base_url, video_id);
+    info!("Fetching video page...");
+    
+    let response = client.get(&url).await?;
+    let consent = is_consent_url(response.url());
+    if !consent {
+        check_watch_url(response.url(), base_url)?;
+    }
+
+    let limit = client.options().max_page_size;
+    let mut html = read_text(response, limit).await?;
+
+    // Accept the consent prompt by sending the cookie it would have set, then ask again
+    if consent || is_consent_page(&html) {
+        info!("Got the consent page, retrying with consent cookies...");
+        let response = client.get_with_cookie(&url, Some(CONSENT_COOKIES)).await?;
+        check_watch_url(response.url(), base_url)?;
+        html = read_text(response, limit).await?;
+    }
+
+    Ok(html)
+}
+
+pub(crate) fn parse_player_response(html: &str) -> Result<serde_json::Value, TranscriptError> {
+    debug!("Extracting caption data...");
+    let json_str = extract_json(html).ok_or(TranscriptError::PlayerDataNotFound)?;
+    
+    let parsed = serde_json::from_str(json_str)?;
+    check_playability(&parsed)?;
+    Ok(parsed)
+}
+
+// The baseUrl of the caption track to download from a player response
+// When `translate` is set, the URL asks YouTube to machine-translate the track
+// into that language, which only works for tracks marked isTranslatable
+pub(crate) fn caption_base_url(
+    parsed: &serde_json::Value,
+    lang: Option<&str>,
+    translate: Option<&str>,
+) -> Result<String, TranscriptError> {
+    // Videos with captions turned off have no captionTracks at all (usually no
+    // captions object either), while an empty list means there's simply no track
+    // A live stream that offers live captions is downloaded as far as it goes
+    let captions = match caption_tracks(parsed) {
+        Some(captions) if !captions.is_empty() => captions,
+        _ if is_live_now(parsed) => return Err(TranscriptError::LiveStreamUnsupported),
+        Some(_) => return Err(TranscriptError::NoCaptionTracks),
+        None => return Err(TranscriptError::CaptionsDisabled),
+    };
+
+    debug!("Found caption tracks...");
+    if let Some(track) = select_caption_track(captions, lang)? {
+        if let Some(base_url) = track.get("baseUrl").and_then(|u| u.as_str()) {
+            let Some(target) = translate else {
+                return Ok(base_url.to_string());
+            };
+
+            if track.get("isTranslatable").and_then(|t| t.as_bool()) != Some(true) {
+                return Err(TranscriptError::NotTranslatable {
+                    language: track
+                        .get("languageCode")
+                        .and_then(|c| c.as_str())
+                        .unwrap_or("unknown")
+                        .to_string(),
+                });
+            }
+            return Ok(with_query_param(base_url, "tlang", target));
+        }
+    }
+
+    Err(TranscriptError::NoCaptionTracks)
+}
+
+// Append `key=value` to a URL's query string
+pub fn with_query_param(url: &str, key: &str, value: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}{}={}", url, separator, key, value)
+}
+
+// Parse a downloaded caption track, treating a track without any lines as an error
+// Tracks are requested as json3, but YouTube may still answer with the XML format,
+// so the body decides which parser is used
+// min_confidence only affects json3 responses, XML ones carry no confidence values
+pub(crate) fn parse_caption_response(
+    body: &str,
+    min_confidence: Option<f64>,
+) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    debug!("Parsing transcript data...");
+    if body.trim().is_empty() {
+        return Err(TranscriptError::EmptyCaptionResponse);
+    }
+    let transcript = if body.trim_start().starts_with('{') {
+        parse_json3_transcript_with(body, min_confidence)?
+    } else {
+        parse_transcript_xml(body)?
+    };
+
+    if transcript.is_empty() {
+        return Err(TranscriptError::EmptyTranscript);
+    }
+
+    info!("Successfully parsed {} lines", transcript.len());
+    Ok(transcript)
+}
+
+// Download and parse the caption track chosen from an already fetched player response
+async fn download_transcript(
+    client: &HttpClient,
+    parsed: &serde_json::Value,
+    lang: Option<&str>,
+    translate: Option<&str>,
+) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    let base_url = caption_base_url(parsed, lang, translate)?;
+
+    // YouTube now and then answers a valid caption URL with an empty body, which
+    // usually goes away when asked again
+    let mut attempt = 0;
+    loop {
+        info!("Downloading transcript...");
+        let result = match client.options().limit {
+            Some(limit) => {
+                let response = client.get(&json3_url(&base_url)).await?;
+                read_first_entries(response, client.options(), limit).await
+            }
+            None => {
+                let body = client.get_text_memoized(&json3_url(&base_url)).await?;
+                parse_caption_response(&body, client.options().min_confidence)
+            }
+        };
+
+        match result {
+            Err(TranscriptError::EmptyCaptionResponse) if attempt < client.options().retries => {
+                let delay = jittered_backoff_delay(attempt, jitter_seed());
+                warn!("Caption response was empty, retrying in {:.1}s...", delay.as_secs_f64());
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Parse a caption body only until `limit` entries have come out of it, leaving the
+// rest unread. json3 is parsed as it arrives; XML still has to be read to the end
+// What is read still counts against FetchOptions::max_caption_size
+async fn read_first_entries(
+    mut response: Response,
+    options: &FetchOptions,
+    limit: usize,
+) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    let max_size = options.max_caption_size;
+    if response.content_length().is_some_and(|len| len > max_size as u64) {
+        return Err(TranscriptError::ResponseTooLarge { limit: max_size });
+    }
+    let mut reader = CaptionReader::Empty(options.min_confidence);
+    let mut transcript = Vec::new();
+    let mut read = 0;
+    while transcript.len() < limit {
+        match response.chunk().await? {
+            Some(chunk) => {
+                read += chunk.len();
+                if read > max_size {
+                    return Err(TranscriptError::ResponseTooLarge { limit: max_size });
+                }
+                transcript.extend(reader.push(&chunk)?);
+            }
+            None => {
+                transcript.extend(reader.finish()?);
+                break;
+            }
+        }
+    }
+    transcript.truncate(limit);
+    Ok(transcript)
+}
+
+// List the caption tracks a video offers without downloading any of them
+pub async fn list_captions(video_id: &str) -> Result<Vec<CaptionTrackInfo>, TranscriptError> {
+    list_captions_with(video_id, &FetchOptions::default()).await
+}
+
+pub async fn list_captions_with(
+    video_id: &str,
+    options: &FetchOptions,
+) -> Result<Vec<CaptionTrackInfo>, TranscriptError> {
+    list_captions_with_client(&HttpClient::new(options)?, video_id).await
+}
+
+pub(crate) async fn list_captions_with_client(
+    client: &HttpClient,
+    video_id: &str,
+) -> Result<Vec<CaptionTrackInfo>, TranscriptError> {
+    let parsed = fetch_player_response(client, video_id).await?;
+    Ok(parse_caption_tracks(&parsed))
+}
+
+// The caption tracks listed in a watch page that was already downloaded (or
+// saved to disk), without any network access
+pub fn available_languages(html: &str) -> Result<Vec<CaptionTrackInfo>, TranscriptError> {
+    Ok(parse_caption_tracks(&parse_player_response(html)?))
+}
+
+/// Fetch the transcript of a video using whichever caption track YouTube lists first
+///
+/// ```no_run
+/// # async fn run() -> Result<(), youtube_transcript::TranscriptError> {
+/// let transcript = youtube_transcript::fetch_transcript("dQw4w9WgXcQ").await?;
+/// for item in &transcript {
+///     println!("{} {}", item.format_time(), item.text);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn fetch_transcript(video_id: &str) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    fetch_transcript_with_lang(video_id, None).await
+}
+
+// Same as fetch_transcript, but prefers the caption track matching `lang` when there is one
+pub async fn fetch_transcript_with_lang(
+    video_id: &str,
+    lang: Option<&str>,
+) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    let (_, transcript) = fetch_video(video_id, lang, &FetchOptions::default()).await?;
+    Ok(transcript)
+}
+
+// Stream the transcript of a video entry by entry instead of returning a Vec
+// A json3 caption track is parsed while it downloads: each entry is handed over
+// as soon as its event has arrived, and more of the body is only read once the
+// consumer asks for the next entry, so a slow consumer holds the pace. XML tracks
+// are parsed in one go once complete. The entries are the same fetch_video gives,
+// and it uses and fills the cache the same way. An error ends the stream
+pub fn fetch_transcript_stream<'a>(
+    video_id: &'a str,
+    lang: Option<&'a str>,
+    options: &'a FetchOptions,
+) -> impl Stream<Item = Result<TranscriptItem, TranscriptError>> + 'a {
+    stream::once(open_transcript_stream(video_id, lang, options))
+        .map_ok(|state| stream::try_unfold(state, TranscriptStream::next))
+        .try_flatten()
+        .take(options.limit.unwrap_or(usize::MAX))
+}
+
+// Parses a caption body as it comes in, telling json3 from XML by its first byte
+enum CaptionReader {
+    // Nothing but whitespace so far
+    Empty(Option<f64>),
+    Json3(Json3Splitter),
+    // XML has to be complete before it's parsed
+    Xml(Vec<u8>),
+}
+
+impl CaptionReader {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<TranscriptItem>, TranscriptError> {
+        if let CaptionReader::Empty(min_confidence) = *self {
+            match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+                Some(b'{') => *self = CaptionReader::Json3(Json3Splitter::new(min_confidence)),
+                Some(_) => *self = CaptionReader::Xml(Vec::new()),
+                None => return Ok(Vec::new()),
+            }
+        }
+        match self {
+            CaptionReader::Json3(splitter) => splitter.push(bytes),
+            CaptionReader::Xml(body) => {
+                body.extend_from_slice(bytes);
+                Ok(Vec::new())
+            }
+            CaptionReader::Empty(_) => Ok(Vec::new()),
+        }
+    }
+
+    // The entries left once the body is complete
+    fn finish(self) -> Result<Vec<TranscriptItem>, TranscriptError> {
+        match self {
+            CaptionReader::Empty(_) => Err(TranscriptError::EmptyCaptionResponse),
+            CaptionReader::Json3(splitter) => splitter.finish().map(|()| Vec::new()),
+            CaptionReader::Xml(body) => parse_caption_response(&String::from_utf8_lossy(&body), None),
+        }
+    }
+}
+
+// Where a streamed transcript is cached once the stream has been read to the end
+struct StreamCache {
+    dir: PathBuf,
+    video_id: String,
+    variant: String,
+    metadata: VideoMetadata,
+    transcript: Vec<TranscriptItem>,
+}
+
+// What fetch_transcript_stream keeps between entries
+struct TranscriptStream {
+    // The caption response still being read, None once it's done (or for a
+    // transcript that came from the cache)
+    body: Option<(Response, CaptionReader)>,
+    read: usize,
+    limit: usize,
+    // Entries parsed but not handed over yet
+    pending: VecDeque<TranscriptItem>,
+    cache: Option<StreamCache>,
+}
+
+impl TranscriptStream {
+    async fn next(mut self) -> Result<Option<(TranscriptItem, Self)>, TranscriptError> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                if let Some(cache) = &mut self.cache {
+                    cache.transcript.push(item.clone());
+                }
+                return Ok(Some((item, self)));
+            }
+            if !self.read_more().await? {
+                if let Some(cache) = self.cache.take() {
+                    let StreamCache { dir, video_id, variant, metadata, transcript } = cache;
+                    if let Err(e) = write_cache(&dir, &video_id, &variant, &metadata, &transcript) {
+                        warn!("Could not cache transcript: {}", e);
+                    }
+                }
+                return Ok(None);
+            }
+        }
+    }
+
+    // Read the next piece of the body, false when there's nothing left to read
+    async fn read_more(&mut self) -> Result<bool, TranscriptError> {
+        let Some((response, reader)) = &mut self.body else {
+            return Ok(false);
+        };
+        match response.chunk().await? {
+            Some(chunk) => {
+                self.read += chunk.len();
+                if self.read > self.limit {
+                    return Err(TranscriptError::ResponseTooLarge { limit: self.limit });
+                }
+                self.pending.extend(reader.push(&chunk)?);
+            }
+            None => {
+                let (_, reader) = self.body.take().unwrap();
+                self.pending.extend(reader.finish()?);
+            }
+        }
+        Ok(true)
+    }
+
+    // Read until something other than whitespace has arrived; a body without
+    // any is an EmptyCaptionResponse
+    async fn read_start(&mut self) -> Result<(), TranscriptError> {
+        while matches!(&self.body, Some((_, CaptionReader::Empty(_)))) {
+            self.read_more().await?;
+        }
+        Ok(())
+    }
+}
+
+async fn open_transcript_stream(
+    video_id: &str,
+    lang: Option<&str>,
+    options: &FetchOptions,
+) -> Result<TranscriptStream, TranscriptError> {
+    validate_video_id(video_id)?;
+    let variant = cache_variant(lang, options);
+    if let Some(dir) = &options.cache_dir {
+        if let Some((_, cached)) = read_cache(dir, video_id, &variant, options.cache_ttl) {
+            info!("Using cached transcript for {}", video_id);
+            return Ok(TranscriptStream {
+                body: None,
+                read: 0,
+                limit: options.max_caption_size,
+                pending: cached.into(),
+                cache: None,
+            });
+        }
+    }
+
+    let client = HttpClient::new(options)?;
+    let (html, parsed) = fetch_player_data(&client, video_id).await?;
+    let base_url = caption_base_url(&parsed, lang, options.translate.as_deref())?;
+    let cache = options.cache_dir.as_ref().map(|dir| StreamCache {
+        dir: dir.clone(),
+        video_id: video_id.to_string(),
+        variant,
+        metadata: video_metadata(html.as_deref(), &parsed),
+        transcript: Vec::new(),
+    });
+
+    // The start of the body is read right away, so an empty one can be asked for
+    // again (see download_transcript) before any entry has been handed over
+    let mut attempt = 0;
+    loop {
+        info!("Downloading transcript...");
+        let response = client.get(&json3_url(&base_url)).await?;
+        if response.content_length().is_some_and(|len| len > options.max_caption_size as u64) {
+            return Err(TranscriptError::ResponseTooLarge { limit: options.max_caption_size });
+        }
+        let mut state = TranscriptStream {
+            body: Some((response, CaptionReader::Empty(options.min_confidence))),
+            read: 0,
+            limit: options.max_caption_size,
+            pending: VecDeque::new(),
+            cache: None,
+        };
+
+        match state.read_start().await {
+            Err(TranscriptError::EmptyCaptionResponse) if attempt < options.retries => {
+                let delay = jittered_backoff_delay(attempt, jitter_seed());
+                warn!("Caption response was empty, retrying in {:.1}s...", delay.as_secs_f64());
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => {
+                result?;
+                state.cache = cache;
+                return Ok(state);
+            }
+        }
+    }
+}
+
+// Cached copies are kept apart per language, translation and confidence filter
+fn cache_variant(lang: Option<&str>, options: &FetchOptions) -> String {
+    let mut variant = match &options.translate {
+        Some(target) => format!("{}_to_{}", lang.unwrap_or("default"), target),
+        None => lang.unwrap_or("default").to_string(),
+    };
+    if let Some(min) = options.min_confidence {
+        variant.push_str(&format!("_conf{}", min));
+    }
+    variant
+}
+
+// Fetch the timing of every single word, which only the json3 caption format
+// provides. Manually uploaded tracks usually time whole lines, in which case each
+// line comes back as one "word". Word timings aren't cached
+pub async fn fetch_word_timings(
+    video_id: &str,
+    lang: Option<&str>,
+    options: &FetchOptions,
+) -> Result<Vec<WordTiming>, TranscriptError> {
+    fetch_word_timings_with_client(&HttpClient::new(options)?, video_id, lang).await
+}
+
+pub(crate) async fn fetch_word_timings_with_client(
+    client: &HttpClient,
+    video_id: &str,
+    lang: Option<&str>,
+) -> Result<Vec<WordTiming>, TranscriptError> {
+    let options = client.options();
+    let parsed = fetch_player_response(client, video_id).await?;
+    let base_url = caption_base_url(&parsed, lang, options.translate.as_deref())?;
+
+    info!("Downloading word timings...");
+    let body = client.get_text_memoized(&json3_url(&base_url)).await?;
+    if body.trim().is_empty() {
+        return Err(TranscriptError::EmptyCaptionResponse);
+    }
+    let words = parse_json3_words_with(&body, options.min_confidence)?;
+    if words.is_empty() {
+        return Err(TranscriptError::EmptyTranscript);
+    }
+    Ok(words)
+}
+
+// Download a video's caption track exactly as YouTube serves it, in its XML
+// format and without any parsing (or decoding), for debugging and archiving.
+// Not cached
+pub async fn fetch_raw_captions(
+    video_id: &str,
+    lang: Option<&str>,
+    options: &FetchOptions,
+) -> Result<Vec<u8>, TranscriptError> {
+    fetch_raw_captions_with_client(&HttpClient::new(options)?, video_id, lang).await
+}
+
+pub(crate) async fn fetch_raw_captions_with_client(
+    client: &HttpClient,
+    video_id: &str,
+    lang: Option<&str>,
+) -> Result<Vec<u8>, TranscriptError> {
+    let parsed = fetch_player_response(client, video_id).await?;
+    let base_url = caption_base_url(&parsed, lang, client.options().translate.as_deref())?;
+
+    info!("Downloading raw captions...");
+    client.get_bytes_memoized(&base_url).await
+}
+
+// The metadata of a video from its player response, with the chapter markers of
+// the watch page when there is one; the player API has no such data
+fn video_metadata(html: Option<&str>, parsed: &serde_json::Value) -> VideoMetadata {
+    let mut metadata = parse_video_metadata(parsed);
+    let markers = html.map(parse_chapter_markers).unwrap_or_default();
+    if !markers.is_empty() {
+        metadata.chapters = markers;
+    }
+    metadata
+}
+
+// Fetch the video's metadata together with its transcript, both taken from
+// the same watch page so only one page request is made
+// With a cache directory set, a fresh enough cached copy skips the network entirely
+pub async fn fetch_video(
+    video_id: &str,
+    lang: Option<&str>,
+    options: &FetchOptions,
+) -> Result<(VideoMetadata, Vec<TranscriptItem>), TranscriptError> {
+    fetch_video_with_client(&HttpClient::new(options)?, video_id, lang).await
+}
+
+pub(crate) async fn fetch_video_with_client(
+    client: &HttpClient,
+    video_id: &str,
+    lang: Option<&str>,
+) -> Result<(VideoMetadata, Vec<TranscriptItem>), TranscriptError> {
+    let options = client.options();
+    // The ID ends up in the cache file name, so check it before touching the cache
+    validate_video_id(video_id)?;
+
+    let cache_variant = cache_variant(lang, options);
+
+    if let Some(dir) = &options.cache_dir {
+        if let Some((metadata, mut transcript)) =
+            read_cache(dir, video_id, &cache_variant, options.cache_ttl)
+        {
+            info!("Using cached transcript for {}", video_id);
+            transcript.truncate(options.limit.unwrap_or(usize::MAX));
+            return Ok((metadata, transcript));
+        }
+    }
+
+    let (html, parsed) = fetch_player_data(client, video_id).await?;
+    let metadata = video_metadata(html.as_deref(), &parsed);
+    let transcript =
+        download_transcript(client, &parsed, lang, options.translate.as_deref()).await?;
+
+    // A cut short transcript isn't cached, it would stand in for the whole one later
+    if let Some(dir) = options.cache_dir.as_ref().filter(|_| options.limit.is_none()) {
+        if let Err(e) = write_cache(dir, video_id, &cache_variant, &metadata, &transcript) {
+            warn!("Could not cache transcript: {}", e);
+        }
+    }
+
+    Ok((metadata, transcript))
+}
+
+// Fetch the video's metadata together with the transcript of every caption
+// language it offers, in the order the tracks are listed, all from one watch
+// page request. Each language is downloaded on its own, so one that fails
+// leaves the others; its error comes back in its place
+// The cache is read and filled per language, the same as fetch_video does
+pub async fn fetch_video_all_langs(
+    video_id: &str,
+    options: &FetchOptions,
+) -> Result<(VideoMetadata, Vec<LangTranscript>), TranscriptError> {
+    fetch_video_all_langs_with_client(&HttpClient::new(options)?, video_id).await
+}
+
+pub(crate) async fn fetch_video_all_langs_with_client(
+    client: &HttpClient,
+    video_id: &str,
+) -> Result<(VideoMetadata, Vec<LangTranscript>), TranscriptError> {
+    let options = client.options();
+    validate_video_id(video_id)?;
+
+    let (html, parsed) = fetch_player_data(client, video_id).await?;
+    let metadata = video_metadata(html.as_deref(), &parsed);
+
+    let mut codes: Vec<String> = Vec::new();
+    for track in parse_caption_tracks(&parsed) {
+        if !codes.contains(&track.language_code) {
+            codes.push(track.language_code);
+        }
+    }
+    if codes.is_empty() {
+        return Err(TranscriptError::NoCaptionTracks);
+    }
+
+    // A cut short transcript isn't cached, see fetch_video
+    let cache_dir = options.cache_dir.as_ref().filter(|_| options.limit.is_none());
+    let mut transcripts = Vec::new();
+    for lang in codes {
+        let variant = cache_variant(Some(&lang), options);
+        let cached = options
+            .cache_dir
+            .as_ref()
+            .and_then(|dir| read_cache(dir, video_id, &variant, options.cache_ttl));
+        let transcript = match cached {
+            Some((_, mut transcript)) => {
+                info!("Using cached {} transcript for {}", lang, video_id);
+                transcript.truncate(options.limit.unwrap_or(usize::MAX));
+                Ok(transcript)
+            }
+            None => {
+                let translate = options.translate.as_deref();
+                let result = download_transcript(client, &parsed, Some(&lang), translate).await;
+                if let (Ok(transcript), Some(dir)) = (&result, cache_dir) {
+                    if let Err(e) = write_cache(dir, video_id, &variant, &metadata, transcript) {
+                        warn!("Could not cache transcript: {}", e);
+                    }
+                }
+                result
+            }
+        };
+        transcripts.push(LangTranscript { lang, transcript });
+    }
+    Ok((metadata, transcripts))
+}
+
+// One caption language's transcript as fetch_video_all_langs returns it
+#[derive(Debug)]
+pub struct LangTranscript {
+    pub lang: String,
+    pub transcript: Result<Vec<TranscriptItem>, TranscriptError>,
+}
+
+// Default bucket size used when grouping transcript lines
+pub const DEFAULT_INTERVAL_SECS: u32 = 6;
+
+// Trim the text and squeeze every run of whitespace, newlines included, into a single space
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Render the transcript as normalized plain text split into the video's chapters,
+// each section headed by "## [MM:SS] Title". Lines before the first chapter come
+// first without a heading; without any chapters this is just to_normalized_text
+pub fn to_chaptered_text(
+    transcript: &[TranscriptItem],
+    chapters: &[Chapter],
+    interval_secs: u32,
+) -> String {
+    let label_format = LabelFormat::default();
+    to_chaptered_text_with(transcript, chapters, interval_secs, false, false, label_format)
+}
+
+// Same as to_chaptered_text, optionally aligning the buckets of every section
+// to that section's first line (see normalize_timestamps_with) or keeping the
+// original line order within each section (see normalize_items_with), with the
+// timestamps, headings included, written in `label_format`
+pub fn to_chaptered_text_with(
+    transcript: &[TranscriptItem],
+    chapters: &[Chapter],
+    interval_secs: u32,
+    align_to_first: bool,
+    keep_order: bool,
+    label_format: LabelFormat,
+) -> String {
+    let normalize = |items: &[TranscriptItem]| {
+        to_normalized_text_with(items, interval_secs, align_to_first, keep_order, label_format)
+    };
+    let Some(first) = chapters.first() else {
+        return normalize(transcript);
+    };
+
+    let mut sections = Vec::new();
+    let intro: Vec<TranscriptItem> =
+        transcript.iter().filter(|item| item.start < first.start).cloned().collect();
+    if !intro.is_empty() {
+        sections.push(normalize(&intro));
+    }
+
+    for (i, chapter) in chapters.iter().enumerate() {
+        let end = chapters.get(i + 1).map_or(f64::INFINITY, |next| next.start);
+        let items: Vec<TranscriptItem> = transcript
+            .iter()
+            .filter(|item| item.start >= chapter.start && item.start < end)
+            .cloned()
+            .collect();
+        sections.push(format!(
+            "## {} {}\n{}",
+            label_format.label(chapter.start),
+            chapter.title,
+            normalize(&items)
+        ));
+    }
+
+    sections.join("\n")
+}
+
+// Render the transcript as normalized plain text: `[MM:SS] text` lines grouped
+// into buckets of `interval_secs` seconds
+pub fn to_normalized_text(transcript: &[TranscriptItem], interval_secs: u32) -> String {
+    to_normalized_text_with(transcript, interval_secs, false, false, LabelFormat::default())
+}
+
+// Same as to_normalized_text, optionally aligning the buckets to the first
+// line (see normalize_timestamps_with) or keeping the original line order
+// (see normalize_items_with), with the timestamps written in `label_format`
+pub fn to_normalized_text_with(
+    transcript: &[TranscriptItem],
+    interval_secs: u32,
+    align_to_first: bool,
+    keep_order: bool,
+    label_format: LabelFormat,
+) -> String {
+    normalize_items_with(transcript, interval_secs, align_to_first, keep_order)
+        .iter()
+        .map(|item| format!("{} {}\n", label_format.label(item.start), item.text))
+        .collect()
+}
+
+// Group transcript entries into buckets of `interval_secs` seconds, the same
+// way normalize_timestamps groups text lines, but without going through text
+// Entries are placed by their start in whole seconds; each bucket becomes one
+// entry starting at the bucket's start and running until its last line ends.
+// An interval of 0 keeps every entry, with its start rounded down
+pub fn normalize_items(items: &[TranscriptItem], interval_secs: u32) -> Vec<TranscriptItem> {
+    normalize_items_with(items, interval_secs, false, false)
+}
+
+// Same as normalize_items, optionally aligning the buckets to the first entry
+// (see normalize_timestamps_with)
+// With `keep_order` the entries aren't sorted by start first. Captions of
+// overlapping speakers are sometimes deliberately out of order, and sorting
+// would scramble the turns; then only neighbouring entries in the same bucket
+// are joined, so a bucket can show up more than once
+// An entry starting a new speaker's turn (">> ...") is never joined onto the
+// line before it, it gets a line of its own labelled with the same bucket
+pub fn normalize_items_with(
+    items: &[TranscriptItem],
+    interval_secs: u32,
+    align_to_first: bool,
+    keep_order: bool,
+) -> Vec<TranscriptItem> {
+    let mut entries: Vec<(i64, &TranscriptItem)> =
+        items.iter().map(|item| (item.start.max(0.0).floor() as i64, item)).collect();
+    if !keep_order {
+        // Stable, so entries sharing a second keep their order
+        entries.sort_by_key(|(secs, _)| *secs);
+    }
+
+    let interval = i64::from(interval_secs);
+    if interval == 0 {
+        return entries
+            .into_iter()
+            .map(|(secs, item)| TranscriptItem {
+                text: collapse_whitespace(&item.text),
+                start: secs as f64,
+                duration: item.duration,
+                index: item.index,
+            })
+            .collect();
+    }
+
+    // Aligned buckets start at the earliest entry, which with `keep_order`
+    // needn't be the first one
+    let base = match entries.iter().map(|(secs, _)| *secs).min() {
+        Some(earliest) if align_to_first => earliest,
+        _ => 0,
+    };
+
+    let mut normalized: Vec<TranscriptItem> = Vec::new();
+    let mut current_bucket = None;
+    for (secs, item) in entries {
+        let bucket = base + (secs - base) / interval * interval;
+        let text = collapse_whitespace(&item.text);
+        if text.is_empty() {
+            continue;
+        }
+
+        match normalized.last_mut() {
+            Some(last) if current_bucket == Some(bucket) && !starts_speaker_turn(&text) => {
+                last.text.push(' ');
+                last.text.push_str(&text);
+                last.duration = last.duration.max(item.end() - last.start);
+            }
+            _ => {
+                normalized.push(TranscriptItem {
+                    text,
+                    start: bucket as f64,
+                    duration: (item.end() - bucket as f64).max(0.0),
+                    index: item.index,
+                });
+                current_bucket = Some(bucket);
+            }
+        }
+    }
+
+    normalized
+}
+
+// Write a file so readers only ever see it complete: the contents go to a
+// temporary file next to it first, which is renamed over the target once it's
+// fully written. A write that fails part-way removes the temporary file again
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let file_name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name")
+    })?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = fs::File::create(&temp_path).and_then(|mut file| {
+        file.write_all(contents.as_ref())?;
+        file.sync_all()
+    });
+    match result.and_then(|()| fs::rename(&temp_path, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+pub fn save_transcript(
+    transcript: &[TranscriptItem],
+    path: &Path,
+    interval_secs: u32,
+) -> Result<(), TranscriptError> {
+    // Save the normalized version
+    write_atomic(path, to_normalized_text(transcript, interval_secs))?;
+    Ok(())
+}
+
+pub fn process_timestamp_line(line: &str) -> Option<(f64, String)> {
+    if let Some(timestamp_end) = line.find(']') {
+        if line.starts_with('[') {
+            let timestamp_str = &line[1..timestamp_end];
+            let text = line[timestamp_end + 1..].trim().to_string();
+            
+            // Convert timestamp to seconds, accepting both MM:SS and HH:MM:SS
+            if timestamp_str.contains(':') {
+                return parse_timestamp(timestamp_str).map(|total| (total, text));
+            }
+        }
+    }
+    None
+}
+
+// Convert "SS", "MM:SS" or "HH:MM:SS" (seconds may have a fraction) into seconds
+pub fn parse_timestamp(timestamp: &str) -> Option<f64> {
+    let parts: Vec<&str> = timestamp.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+
+    let mut total = 0.0;
+    for part in parts {
+        total = total * 60.0 + part.parse::<f64>().ok()?;
+    }
+    // "NaN" and "inf" parse as floats too, but aren't timestamps
+    (total.is_finite() && total >= 0.0).then_some(total)
+}
+
+// Normalize timestamps
+// Lines are grouped into buckets of `interval_secs` seconds, each labelled with
+// the bucket's start time. An interval of 0 turns grouping off and keeps every
+// original line with its own timestamp
+pub fn normalize_timestamps(content: &str, interval_secs: u32) -> String {
+    normalize_timestamps_with(content, interval_secs, false, LabelFormat::default())
+}
+
+// Same as normalize_timestamps, but with `align_to_first` the buckets start at
+// the first line's timestamp instead of at 0:00, so a talk starting at 0:14
+// with 6 second buckets is grouped as 0:14, 0:20, ... rather than 0:12, 0:18, ...
+// The bucket labels are written in `label_format`
+#[allow(unused_mut)]
+pub fn normalize_timestamps_with(
+    content: &str,
+    interval_secs: u32,
+    align_to_first: bool,
+    label_format: LabelFormat,
+) -> String {
+    let mut normalized = String::new();
+    let mut current_timestamp = 0;
+    let interval = interval_secs as i32;
+    
+    // Process each line and collect timestamps and text
+    let mut entries: Vec<(f64, String)> = content
+        .lines()
+        .filter_map(process_timestamp_line)
+        .collect();
+    
+    // Nothing to normalize
+    if entries.is_empty() {
+        return normalized;
+    }
+    
+    // Sort by timestamp if needed (total_cmp can't panic, unlike partial_cmp().unwrap())
+    // sort_by is stable, so lines sharing a timestamp keep their original order
+    entries.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if align_to_first {
+        current_timestamp = entries[0].0.floor() as i32;
+    }
+
+    let label = |secs: i32| label_format.label(f64::from(secs));
+
+    // No grouping requested, keep the lines as they are
+    if interval == 0 {
+        for (ts, text) in &entries {
+            normalized.push_str(&format!("{} {}\n", label(*ts as i32), collapse_whitespace(text)));
+        }
+        return normalized;
+    }
+    
+    // Group text into fixed intervals in a single pass: the entries are sorted,
+    // so each bucket's lines come in one run and a bucket is done as soon as a
+    // line past its end shows up
+    let base = current_timestamp;
+    let mut bucket_text: Vec<&str> = Vec::new();
+    for (ts, text) in &entries {
+        let bucket = bucket_start(*ts, base, interval);
+        if bucket != current_timestamp {
+            push_bucket(&mut normalized, &label(current_timestamp), &bucket_text);
+            bucket_text.clear();
+            current_timestamp = bucket;
+        }
+        bucket_text.push(text);
+    }
+    push_bucket(&mut normalized, &label(current_timestamp), &bucket_text);
+    
+    normalized
+}
+
+// Start of the `interval` second bucket (counted from `base`) that a timestamp
+// falls into. The float division is only a first guess, checked against the
+// bucket bounds so a timestamp right at a boundary always lands on its right
+fn bucket_start(ts: f64, base: i32, interval: i32) -> i32 {
+    let mut start = base + ((ts - f64::from(base)) / f64::from(interval)).floor() as i32 * interval;
+    while f64::from(start) > ts {
+        start -= interval;
+    }
+    while f64::from(start + interval) <= ts {
+        start += interval;
+    }
+    start
+}
+
+// Add one normalized line for a bucket's texts, unless they're all blank
+fn push_bucket(normalized: &mut String, label: &str, texts: &[&str]) {
+    let text = collapse_whitespace(&texts.join(" "));
+    if !text.is_empty() {
+        normalized.push_str(&format!("{} {}\n", label, text));
+    }
+}
+// Timstamp line end
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{json3_body, mock_video, MockResponse, MockServer};
+
+    fn item_at(start: f64) -> TranscriptItem {
+        TranscriptItem {
+            text: String::new(),
+            start,
+            duration: 0.0,
+            index: 0,
+        }
+    }
+
+    #[test]
+    fn format_time_rolls_over_to_hours() {
+        assert_eq!(item_at(59.0).format_time(), "[00:59]");
+        assert_eq!(item_at(60.0).format_time(), "[01:00]");
+        assert_eq!(item_at(3599.0).format_time(), "[59:59]");
+        assert_eq!(item_at(3600.0).format_time(), "[01:00:00]");
+        assert_eq!(item_at(7384.0).format_time(), "[02:03:04]");
+    }
+
+    #[test]
+    fn format_time_drops_the_fraction() {
+        assert_eq!(item_at(3725.9).format_time(), "[01:02:05]");
+        assert_eq!(format_seconds(-1.0), "[00:00]");
+    }
+
+    #[test]
+    fn parse_video_id_accepts_every_link_shape() {
+        let id = Some("dQw4w9WgXcQ".to_string());
+        assert_eq!(parse_video_id("https://www.youtube.com/watch?v=dQw4w9WgXcQ"), id);
+        assert_eq!(parse_video_id("https://www.youtube.com/watch?feature=share&v=dQw4w9WgXcQ"), id);
+        assert_eq!(parse_video_id("https://youtu.be/dQw4w9WgXcQ"), id);
+        assert_eq!(parse_video_id("https://www.youtube.com/shorts/dQw4w9WgXcQ"), id);
+        assert_eq!(parse_video_id("https://www.youtube.com/embed/dQw4w9WgXcQ?rel=0"), id);
+        assert_eq!(parse_video_id("  dQw4w9WgXcQ  "), id);
+    }
+
+    #[test]
+    fn parse_video_id_rejects_garbage() {
+        assert_eq!(parse_video_id("not a video"), None);
+        assert_eq!(parse_video_id("https://www.youtube.com/watch?v=short"), None);
+        assert_eq!(parse_video_id(""), None);
+    }
+
+    const SAMPLE: &str = "[00:01] one\n[00:04] two\n[00:09] three\n[00:12] four\n[00:25] five\n";
+
+    #[test]
+    fn groups_lines_into_ten_second_buckets() {
+        assert_eq!(
+            normalize_timestamps(SAMPLE, 10),
+            "[00:00] one two three\n[00:10] four\n[00:20] five\n"
+        );
+    }
+
+    #[test]
+    fn interval_zero_keeps_every_line() {
+        assert_eq!(normalize_timestamps(SAMPLE, 0), SAMPLE);
+    }
+
+    #[test]
+    fn default_interval_is_six_seconds() {
+        assert_eq!(
+            normalize_timestamps(SAMPLE, DEFAULT_INTERVAL_SECS),
+            "[00:00] one two\n[00:06] three\n[00:12] four\n[00:24] five\n"
+        );
+    }
+
+    #[test]
+    fn sanitized_titles_drop_separators_and_keep_emoji() {
+        assert_eq!(sanitize_filename("AC/DC: Live \\ Loud 🎸"), "ACDC Live Loud 🎸");
+        assert_eq!(sanitize_filename("tab\there\u{7}"), "tabhere");
+        assert_eq!(sanitize_filename(&"x".repeat(200)).chars().count(), MAX_FILENAME_TITLE_LEN);
+    }
+
+    pub(crate) fn options_for(server: &MockServer) -> FetchOptions {
+        FetchOptions {
+            base_url: server.url().to_string(),
+            ..FetchOptions::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn fetches_from_the_configured_base_url() {
+        let server = MockServer::start();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], &json3_body(&[(0, 1500, "hello")]));
+
+        let (metadata, transcript) =
+            fetch_video("dQw4w9WgXcQ", None, &options_for(&server)).await.unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Mock video"));
+        assert_eq!(transcript.len(), 1);
+        assert_eq!(transcript[0].text, "hello");
+        assert_eq!(server.hits("/watch"), 1);
+    }
+
+    #[tokio::test]
+    async fn a_cached_video_is_read_without_the_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = MockServer::start();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], &json3_body(&[(0, 1500, "hello")]));
+        let options = FetchOptions {
+            cache_dir: Some(dir.path().to_path_buf()),
+            ..options_for(&server)
+        };
+        let fetched = fetch_video("dQw4w9WgXcQ", None, &options).await.unwrap();
+
+        // Nothing listens on the discard port, so any request would fail
+        let offline = FetchOptions {
+            base_url: "http://127.0.0.1:9".to_string(),
+            ..options
+        };
+        let (metadata, transcript) = fetch_video("dQw4w9WgXcQ", None, &offline).await.unwrap();
+        assert_eq!(metadata.title, fetched.0.title);
+        assert_eq!(transcript, fetched.1);
+        assert_eq!(server.hits("/watch"), 1);
+
+        let uncached = FetchOptions {
+            cache_dir: None,
+            ..offline
+        };
+        assert!(fetch_video("dQw4w9WgXcQ", None, &uncached).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_limit_keeps_the_first_entries_and_caches_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = MockServer::start();
+        let lines: Vec<(u64, u64, String)> =
+            (0..40).map(|i| (i * 2000, 2000, format!("line {}", i))).collect();
+        let lines: Vec<(u64, u64, &str)> = lines.iter().map(|(s, d, t)| (*s, *d, t.as_str())).collect();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], &json3_body(&lines));
+        let options = FetchOptions {
+            cache_dir: Some(dir.path().to_path_buf()),
+            limit: Some(5),
+            ..options_for(&server)
+        };
+
+        let (_, transcript) = fetch_video("dQw4w9WgXcQ", None, &options).await.unwrap();
+        let texts: Vec<&str> = transcript.iter().map(|item| item.text.as_str()).collect();
+        assert_eq!(texts, ["line 0", "line 1", "line 2", "line 3", "line 4"]);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_caption_track_over_max_caption_size_is_refused() {
+        let server = MockServer::start();
+        let lines: Vec<(u64, u64, &str)> = (0..40).map(|i| (i * 2000, 2000, "a line")).collect();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], &json3_body(&lines));
+
+        for limit in [None, Some(5)] {
+            let options = FetchOptions {
+                max_caption_size: 100,
+                limit,
+                ..options_for(&server)
+            };
+            let error = fetch_video("dQw4w9WgXcQ", None, &options).await.unwrap_err();
+            assert!(matches!(error, TranscriptError::ResponseTooLarge { limit: 100 }), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn detects_the_consent_interstitial() {
+        assert!(is_consent_page(include_str!("../tests/fixtures/consent_page.html")));
+        let player = crate::mock_server::player_response("http://x", "dQw4w9WgXcQ", &["en"]);
+        assert!(!is_consent_page(&crate::mock_server::watch_page(&player)));
+    }
+
+    #[tokio::test]
+    async fn retries_with_consent_cookies_after_the_interstitial() {
+        let server = MockServer::start();
+        // The interstitial is queued first, so the watch page comes second
+        let consent = include_str!("../tests/fixtures/consent_page.html");
+        server.mock("/watch?v=dQw4w9WgXcQ", crate::mock_server::MockResponse::ok(consent));
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], &json3_body(&[(0, 1000, "past consent")]));
+
+        let (_, transcript) = fetch_video("dQw4w9WgXcQ", None, &options_for(&server)).await.unwrap();
+        assert_eq!(transcript[0].text, "past consent");
+        let watch_requests: Vec<_> =
+            server.requests().into_iter().filter(|r| r.path() == "/watch").collect();
+        assert_eq!(watch_requests.len(), 2);
+        assert!(watch_requests[1].header("cookie").is_some_and(|c| c.contains("CONSENT=YES")));
+    }
+
+    #[test]
+    fn extract_json_balances_braces_whatever_follows() {
+        let pages = [
+            r#"<script>var ytInitialPlayerResponse = {"a":{"b":1}};</script>"#,
+            "<script>var ytInitialPlayerResponse = {\"a\":{\"b\":1}};\n</script>",
+            "<script>var ytInitialPlayerResponse = {\"a\":{\"b\":1}}  ;  \n  </script>",
+            r#"<script>var ytInitialPlayerResponse = {"a":{"b":1}};var meta = {"c":2};</script>"#,
+        ];
+        for html in pages {
+            assert_eq!(extract_json(html), Some(r#"{"a":{"b":1}}"#), "{}", html);
+        }
+    }
+
+    #[test]
+    fn extract_json_ignores_braces_inside_strings() {
+        let html = r#"var ytInitialPlayerResponse = {"title":"a } \" { b","n":{}};</script>"#;
+        assert_eq!(extract_json(html), Some(r#"{"title":"a } \" { b","n":{}}"#));
+        assert_eq!(extract_json("var ytInitialPlayerResponse = {\"cut\": "), None);
+    }
+
+    // Progress goes through the log macros; only the binary decides what reaches stdout
+    #[test]
+    fn library_code_never_prints() {
+        let sources = [
+            ("analysis.rs", include_str!("analysis.rs")),
+            ("blocking.rs", include_str!("blocking.rs")),
+            ("cache.rs", include_str!("cache.rs")),
+            ("captions.rs", include_str!("captions.rs")),
+            ("channel.rs", include_str!("channel.rs")),
+            ("chapters.rs", include_str!("chapters.rs")),
+            ("cookies.rs", include_str!("cookies.rs")),
+            ("error.rs", include_str!("error.rs")),
+            ("fetcher.rs", include_str!("fetcher.rs")),
+            ("format.rs", include_str!("format.rs")),
+            ("http.rs", include_str!("http.rs")),
+            ("innertube.rs", include_str!("innertube.rs")),
+            ("json3.rs", include_str!("json3.rs")),
+            ("lib.rs", include_str!("lib.rs")),
+            ("metadata.rs", include_str!("metadata.rs")),
+            ("playability.rs", include_str!("playability.rs")),
+            ("playlist.rs", include_str!("playlist.rs")),
+            ("subtitles.rs", include_str!("subtitles.rs")),
+            ("transform.rs", include_str!("transform.rs")),
+            ("xml.rs", include_str!("xml.rs")),
+        ];
+        // Spelled in pieces so this test doesn't find itself
+        let macros = [concat!("print", "ln!"), concat!("print", "!("), concat!("eprint", "ln!")];
+        for (name, source) in sources {
+            for (number, line) in source.lines().enumerate() {
+                // Doc examples may print, they aren't library code
+                if line.trim_start().starts_with("//") {
+                    continue;
+                }
+                for m in macros {
+                    assert!(!line.contains(m), "{}:{} prints: {}", name, number + 1, line.trim());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn joins_grouped_lines_with_single_spaces() {
+        let items = [
+            TranscriptItem { text: "  so this\nis ".to_string(), ..item_at(1.0) },
+            TranscriptItem { text: "\nwhere   we\n  start".to_string(), ..item_at(2.0) },
+            TranscriptItem { text: "   ".to_string(), ..item_at(3.0) },
+        ];
+        assert_eq!(to_normalized_text(&items, 6), "[00:00] so this is where we start\n");
+        assert_eq!(
+            normalize_timestamps("[00:01]   padded   text  \n[00:02]more\n", 6),
+            "[00:00] padded text more\n"
+        );
+    }
+
+    #[test]
+    fn normalizing_nothing_gives_nothing() {
+        assert_eq!(normalize_timestamps("", 6), "");
+        assert_eq!(normalize_timestamps("no timestamps here\n\n", 6), "");
+        assert!(normalize_items(&[], 6).is_empty());
+    }
+
+    #[test]
+    fn malformed_timestamps_are_skipped_without_panicking() {
+        let content = "[00:NaN] bad\n[inf:00] worse\n[00:03] good\n[-1:00] negative\n";
+        assert_eq!(normalize_timestamps(content, 6), "[00:00] good\n");
+        assert_eq!(parse_timestamp("NaN"), None);
+
+        // A NaN start can't reach the text parser, but items can hold one
+        let items = [item_at(f64::NAN), TranscriptItem { text: "ok".to_string(), ..item_at(2.0) }];
+        let normalized = normalize_items(&items, 6);
+        assert_eq!(normalized.last().map(|item| item.text.as_str()), Some("ok"));
+    }
+
+    #[tokio::test]
+    async fn translation_adds_tlang_to_the_caption_request() {
+        let server = MockServer::start();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], "");
+        server.mock(
+            "/api/timedtext?v=dQw4w9WgXcQ&lang=en&tlang=fr&fmt=json3",
+            MockResponse::ok(json3_body(&[(0, 1500, "bonjour")])),
+        );
+        let options = FetchOptions {
+            translate: Some("fr".to_string()),
+            ..options_for(&server)
+        };
+
+        let (_, transcript) = fetch_video("dQw4w9WgXcQ", Some("en"), &options).await.unwrap();
+        assert_eq!(transcript[0].text, "bonjour");
+        assert_eq!(server.hits("/api/timedtext?v=dQw4w9WgXcQ&lang=en&tlang=fr&fmt=json3"), 1);
+    }
+
+    #[test]
+    fn only_translatable_tracks_can_be_translated() {
+        let parsed: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/player_multi_track.json")).unwrap();
+        assert!(matches!(
+            caption_base_url(&parsed, Some("es-419"), Some("fr")),
+            Err(TranscriptError::NotTranslatable { language }) if language == "es-419"
+        ));
+        assert!(caption_base_url(&parsed, Some("de"), Some("fr")).unwrap().ends_with("&tlang=fr"));
+    }
+
+    #[test]
+    fn precise_times_keep_the_milliseconds() {
+        assert_eq!(item_at(65.25).format_time_precise(), "[01:05.250]");
+        assert_eq!(item_at(0.0).format_time_precise(), "[00:00.000]");
+        assert_eq!(item_at(3725.5).format_time_precise(), "[01:02:05.500]");
+    }
+
+    #[test]
+    fn precise_times_round_up_into_the_next_second() {
+        assert_eq!(item_at(1.9994).format_time_precise(), "[00:01.999]");
+        assert_eq!(item_at(1.9995).format_time_precise(), "[00:02.000]");
+        assert_eq!(item_at(59.9995).format_time_precise(), "[01:00.000]");
+        assert_eq!(item_at(3599.9995).format_time_precise(), "[01:00:00.000]");
+    }
+
+    #[test]
+    fn timestamps_parse_as_seconds_minutes_or_hours() {
+        assert_eq!(parse_timestamp("90"), Some(90.0));
+        assert_eq!(parse_timestamp("01:05"), Some(65.0));
+        assert_eq!(parse_timestamp("1:02:03.5"), Some(3723.5));
+        assert_eq!(parse_timestamp("1:2:3:4"), None);
+        assert_eq!(parse_timestamp("ab:cd"), None);
+        assert_eq!(parse_timestamp("-5"), None);
+    }
+
+    async fn collect_stream(
+        video_id: &str,
+        options: &FetchOptions,
+    ) -> Result<Vec<TranscriptItem>, TranscriptError> {
+        fetch_transcript_stream(video_id, None, options).try_collect().await
+    }
+
+    #[tokio::test]
+    async fn the_stream_gives_the_same_entries_as_fetch_video() {
+        let lines: Vec<(u64, u64, String)> =
+            (0..5000).map(|i| (i * 1000, 900, format!("line {} {{\"quoted\"}}", i))).collect();
+        let lines: Vec<(u64, u64, &str)> =
+            lines.iter().map(|(start, dur, text)| (*start, *dur, text.as_str())).collect();
+        let server = MockServer::start();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], "");
+        server.mock(
+            "/api/timedtext?v=dQw4w9WgXcQ&lang=en&fmt=json3",
+            MockResponse::ok(json3_body(&lines)).chunked(),
+        );
+        let options = options_for(&server);
+
+        let (_, eager) = fetch_video("dQw4w9WgXcQ", None, &options).await.unwrap();
+        let streamed = collect_stream("dQw4w9WgXcQ", &options).await.unwrap();
+        assert_eq!(streamed.len(), 5000);
+        assert_eq!(streamed, eager);
+    }
+
+    #[tokio::test]
+    async fn the_stream_handles_xml_tracks_too() {
+        let xml = include_str!("../tests/fixtures/captions_markup.xml");
+        let server = MockServer::start();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], xml);
+
+        let streamed = collect_stream("dQw4w9WgXcQ", &options_for(&server)).await.unwrap();
+        assert_eq!(streamed, parse_transcript_xml(xml).unwrap());
+    }
+
+    #[tokio::test]
+    async fn the_stream_fills_and_reads_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = MockServer::start();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], &json3_body(&[(0, 1500, "hello")]));
+        let options = FetchOptions {
+            cache_dir: Some(dir.path().to_path_buf()),
+            ..options_for(&server)
+        };
+
+        let streamed = collect_stream("dQw4w9WgXcQ", &options).await.unwrap();
+        let again = collect_stream("dQw4w9WgXcQ", &options).await.unwrap();
+        assert_eq!(again, streamed);
+        assert_eq!(server.hits("/watch"), 1);
+    }
+
+    #[tokio::test]
+    async fn the_stream_retries_an_empty_body_and_reports_a_failure_once() {
+        let server = MockServer::start();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], " \n");
+        server.mock(
+            "/api/timedtext?v=dQw4w9WgXcQ&lang=en&fmt=json3",
+            MockResponse::ok(json3_body(&[(0, 1500, "hello")])),
+        );
+        let options = FetchOptions {
+            retries: 1,
+            ..options_for(&server)
+        };
+        let streamed = collect_stream("dQw4w9WgXcQ", &options).await.unwrap();
+        assert_eq!(streamed[0].text, "hello");
+
+        let items: Vec<_> = fetch_transcript_stream("aaaaaaaaaa1", None, &options).collect().await;
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_err());
+    }
+
+    #[test]
+    fn end_is_start_plus_duration() {
+        let item = TranscriptItem {
+            duration: 2.25,
+            ..item_at(65.5)
+        };
+        assert_eq!(item.end(), 67.75);
+        assert_eq!(item_at(3.0).end(), 3.0);
+    }
+
+    #[test]
+    fn lines_sharing_a_timestamp_keep_their_order() {
+        let content = "[00:05] second\n[00:01] first\n[00:05] third\n[00:05] fourth\n";
+        assert_eq!(
+            normalize_timestamps(content, 0),
+            "[00:01] first\n[00:05] second\n[00:05] third\n[00:05] fourth\n"
+        );
+        assert_eq!(normalize_timestamps(content, 10), "[00:00] first second third fourth\n");
+    }
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn atomic_writes_replace_the_whole_file_and_leave_no_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transcript.txt");
+        fs::write(&path, "an older and much longer transcript\n").unwrap();
+
+        let line = TranscriptItem {
+            text: "hello".to_string(),
+            ..item_at(1.0)
+        };
+        save_transcript(&[line], &path, 0).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[00:01] hello\n");
+        assert_eq!(dir_entries(dir.path()), ["transcript.txt"]);
+    }
+
+    #[test]
+    fn a_failed_write_leaves_nothing_half_written_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        // A directory in the way makes the final rename fail after the data is written
+        let path = dir.path().join("transcript.txt");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("keep"), "").unwrap();
+
+        assert!(write_atomic(&path, "new contents").is_err());
+        assert_eq!(dir_entries(dir.path()), ["transcript.txt"]);
+        assert!(path.is_dir());
+    }
+
+    #[test]
+    fn finds_the_player_response_behind_every_known_marker() {
+        let pages = [
+            include_str!("../tests/fixtures/watch_marker_var.html"),
+            include_str!("../tests/fixtures/watch_marker_window.html"),
+            include_str!("../tests/fixtures/watch_marker_bare.html"),
+            include_str!("../tests/fixtures/watch_marker_compact.html"),
+        ];
+        for page in pages {
+            let parsed = parse_player_response(page).unwrap();
+            assert_eq!(parsed["videoDetails"]["videoId"], "dQw4w9WgXcQ");
+        }
+    }
+
+    #[test]
+    fn a_page_without_a_marker_has_no_player_data() {
+        assert!(matches!(
+            parse_player_response("<html><script>var ytInitialData = {};</script></html>"),
+            Err(TranscriptError::PlayerDataNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn an_empty_caption_body_is_retried_then_reported() {
+        let server = MockServer::start();
+        mock_video(&server, "dQw4w9WgXcQ", &["en"], "");
+        let options = FetchOptions {
+            retries: 1,
+            ..options_for(&server)
+        };
+
+        let error = fetch_video("dQw4w9WgXcQ", None, &options).await.unwrap_err();
+        assert!(matches!(error, TranscriptError::EmptyCaptionResponse));
+        assert_eq!(server.hits("/api/timedtext"), 2);
+
+        server.mock(
+            "/api/timedtext?v=dQw4w9WgXcQ&lang=en&fmt=json3",
+            MockResponse::ok(json3_body(&[(0, 1500, "back")])),
+        );
+        let (_, transcript) = fetch_video("dQw4w9WgXcQ", None, &options).await.unwrap();
+        assert_eq!(transcript[0].text, "back");
+    }
+
+    #[test]
+    fn an_empty_body_is_told_apart_from_one_without_lines() {
+        assert!(matches!(
+            parse_caption_response(" \n\t", None),
+            Err(TranscriptError::EmptyCaptionResponse)
+        ));
+        assert!(matches!(
+            parse_caption_response("<transcript></transcript>", None),
+            Err(TranscriptError::EmptyTranscript)
+        ));
+    }
+
+    #[test]
+    fn chapters_head_their_sections() {
+        let transcript: Vec<TranscriptItem> = [("early", 2.0), ("intro", 10.0), ("setup", 70.0)]
+            .into_iter()
+            .map(|(text, start)| TranscriptItem {
+                text: text.to_string(),
+                ..item_at(start)
+            })
+            .collect();
+        let chapters = [
+            Chapter {
+                title: "Intro".to_string(),
+                start: 5.0,
+            },
+            Chapter {
+                title: "Setup".to_string(),
+                start: 65.0,
+            },
+        ];
+        assert_eq!(
+            to_chaptered_text(&transcript, &chapters, 0),
+            "[00:02] early\n\n## [00:05] Intro\n[00:10] intro\n\n## [01:05] Setup\n[01:10] setup\n"
+        );
+        assert_eq!(to_chaptered_text(&transcript, &[], 0), to_normalized_text(&transcript, 0));
+    }
+
+    #[test]
+    fn aligned_chapters_start_their_buckets_at_their_first_line() {
+        let transcript: Vec<TranscriptItem> = [("intro", 10.0), ("more", 14.0), ("setup", 70.0)]
+            .into_iter()
+            .map(|(text, start)| TranscriptItem {
+                text: text.to_string(),
+                ..item_at(start)
+            })
+            .collect();
+        let chapters = [
+            Chapter {
+                title: "Intro".to_string(),
+                start: 5.0,
+            },
+            Chapter {
+                title: "Setup".to_string(),
+                start: 65.0,
+            },
+        ];
+        assert_eq!(
+            to_chaptered_text_with(&transcript, &chapters, 6, false, false, LabelFormat::default()),
+            "## [00:05] Intro\n[00:06] intro\n[00:12] more\n\n## [01:05] Setup\n[01:06] setup\n"
+        );
+        assert_eq!(
+            to_chaptered_text_with(&transcript, &chapters, 6, true, false, LabelFormat::default()),
+            "## [00:05] Intro\n[00:10] intro more\n\n## [01:05] Setup\n[01:10] setup\n"
+        );
+    }
+
+    #[test]
+    fn no_sort_keeps_the_order_within_each_chapter() {
+        let transcript: Vec<TranscriptItem> = [("late", 14.0), ("early", 10.0), ("setup", 70.0)]
+            .into_iter()
+            .map(|(text, start)| TranscriptItem {
+                text: text.to_string(),
+                ..item_at(start)
+            })
+            .collect();
+        let chapters = [Chapter {
+            title: "Intro".to_string(),
+            start: 5.0,
+        }];
+        assert_eq!(
+            to_chaptered_text_with(&transcript, &chapters, 0, false, true, LabelFormat::default()),
+            "## [00:05] Intro\n[00:14] late\n[00:10] early\n[01:10] setup\n"
+        );
+        assert_eq!(
+            to_chaptered_text_with(&transcript, &chapters, 0, false, false, LabelFormat::default()),
+            "## [00:05] Intro\n[00:10] early\n[00:14] late\n[01:10] setup\n"
+        );
+    }
+
+    #[test]
+    fn video_ids_are_eleven_url_safe_characters() {
+        for id in ["dQw4w9WgXcQ", "a-b_c-d_e-f", "00000000000"] {
+            assert!(validate_video_id(id).is_ok(), "{id}");
+        }
+        for id in ["", "dQw4w9WgXc", "dQw4w9WgXcQQ", "dQw4w9WgXc!", "dQw4w9 WgXc", "dQw4w9WgXcé"] {
+            assert!(
+                matches!(validate_video_id(id), Err(TranscriptError::InvalidVideoId(bad)) if bad == id),
+                "{id}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn an_invalid_id_fails_before_any_request() {
+        let server = MockServer::start();
+        let error = fetch_video("not-an-id", None, &options_for(&server)).await.unwrap_err();
+        assert!(matches!(error, TranscriptError::InvalidVideoId(_)));
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn aligned_buckets_start_at_the_first_line() {
+        let content = "[00:12] a\n[00:19] b\n[00:21] c\n[00:23] d\n";
+        assert_eq!(normalize_timestamps(content, 10), "[00:10] a b\n[00:20] c d\n");
+        assert_eq!(
+            normalize_timestamps_with(content, 10, true, LabelFormat::default()),
+            "[00:12] a b c\n[00:22] d\n"
+        );
+    }
+
+    #[test]
+    fn grouping_items_matches_grouping_their_text() {
+        let transcript: Vec<TranscriptItem> = [
+            ("intro", 0.4, 2.0),
+            ("late  line", 13.9, 1.0),
+            ("early", 12.2, 1.5),
+            ("   ", 15.0, 1.0),
+            ("next", 21.0, 2.0),
+            ("much later", 3725.0, 4.0),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, (text, start, duration))| TranscriptItem {
+            text: text.to_string(),
+            start,
+            duration,
+            index,
+        })
+        .collect();
+        let text: String =
+            transcript.iter().map(|item| format!("{} {}\n", item.format_time(), item.text)).collect();
+
+        for interval in [0, 1, 6, 10, 60] {
+            for align in [false, true] {
+                assert_eq!(
+                    to_normalized_text_with(&transcript, interval, align, false, LabelFormat::default()),
+                    normalize_timestamps_with(&text, interval, align, LabelFormat::default()),
+                    "interval {interval}, aligned {align}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn grouped_items_span_their_lines() {
+        let transcript = [
+            TranscriptItem {
+                text: "a".to_string(),
+                duration: 2.0,
+                index: 4,
+                ..item_at(11.5)
+            },
+            TranscriptItem {
+                text: "b".to_string(),
+                duration: 3.0,
+                index: 5,
+                ..item_at(15.0)
+            },
+        ];
+        let grouped = normalize_items(&transcript, 10);
+        assert_eq!(grouped.len(), 1);
+        assert_eq!(grouped[0].text, "a b");
+        assert_eq!((grouped[0].start, grouped[0].duration, grouped[0].index), (10.0, 8.0, 4));
+    }
+
+    #[test]
+    fn t_offsets_parse_in_every_form() {
+        assert_eq!(parse_start_offset("90"), Some(90.0));
+        assert_eq!(parse_start_offset("90s"), Some(90.0));
+        assert_eq!(parse_start_offset("1m30s"), Some(90.0));
+        assert_eq!(parse_start_offset("1h2m3s"), Some(3723.0));
+        assert_eq!(parse_start_offset("2m"), Some(120.0));
+        assert_eq!(parse_start_offset("soon"), None);
+        assert_eq!(parse_start_offset(""), None);
+    }
+
+    #[test]
+    fn links_keep_a_clean_id_and_their_start() {
+        let cases = [
+            ("https://youtu.be/dQw4w9WgXcQ?t=90", Some(90.0)),
+            ("https://youtu.be/dQw4w9WgXcQ?si=abc&t=90s", Some(90.0)),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1m30s", Some(90.0)),
+            ("https://www.youtube.com/watch?t=1h2m3s&v=dQw4w9WgXcQ", Some(3723.0)),
+            ("https://youtu.be/dQw4w9WgXcQ", None),
+            ("dQw4w9WgXcQ", None),
+        ];
+        for (link, start) in cases {
+            assert_eq!(parse_video_link(link), Some(("dQw4w9WgXcQ".to_string(), start)), "{link}");
+        }
+    }
+
+    #[test]
+    fn an_empty_track_list_is_not_the_same_as_disabled_captions() {
+        let empty: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/player_empty_tracks.json")).unwrap();
+        assert!(matches!(caption_base_url(&empty, None, None), Err(TranscriptError::NoCaptionTracks)));
+
+        let disabled: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/player_captions_disabled.json")).unwrap();
+        assert!(matches!(
+            caption_base_url(&disabled, None, None),
+            Err(TranscriptError::CaptionsDisabled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn follows_a_redirect_chain_to_the_watch_page() {
+        let server = MockServer::start();
+        let player = crate::mock_server::player_response(server.url(), "dQw4w9WgXcQ", &["en"]);
+        server.mock("/watch?v=dQw4w9WgXcQ", MockResponse::redirect("/region?next=watch"));
+        server.mock("/region?next=watch", MockResponse::redirect("/watch?v=dQw4w9WgXcQ&gl=DE"));
+        server.mock(
+            "/watch?v=dQw4w9WgXcQ&gl=DE",
+            MockResponse::ok(crate::mock_server::watch_page(&player)),
+        );
+        server.mock(
+            "/api/timedtext?v=dQw4w9WgXcQ&lang=en&fmt=json3",
+            MockResponse::ok(json3_body(&[(0, 1000, "after the bounces")])),
+        );
+
+        let (_, transcript) = fetch_video("dQw4w9WgXcQ", None, &options_for(&server)).await.unwrap();
+        assert_eq!(transcript[0].text, "after the bounces");
+        assert_eq!(server.hits("/region"), 1);
+    }
+
+    #[tokio::test]
+    async fn a_redirect_away_from_the_watch_page_is_an_error() {
+        let server = MockServer::start();
+        server.mock("/watch?v=dQw4w9WgXcQ", MockResponse::redirect("/region?next=watch"));
+        server.mock("/region?next=watch", MockResponse::redirect("/sorry/index"));
+        server.mock("/sorry/index", MockResponse::ok("<html>unusual traffic</html>"));
+
+        let err = fetch_video("dQw4w9WgXcQ", None, &options_for(&server)).await.unwrap_err();
+        match err {
+            TranscriptError::UnexpectedRedirect { url } => assert!(url.ends_with("/sorry/index"), "{}", url),
+            other => panic!("expected UnexpectedRedirect, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn no_sort_keeps_the_caption_order_of_the_xml() {
+        let xml = r#"<transcript>
+            <text start="10" dur="2">Interviewer: so then</text>
+            <text start="4" dur="3">Guest: as I said</text>
+            <text start="12" dur="1">Interviewer: right</text>
+        </transcript>"#;
+        let transcript = parse_transcript_xml(xml).unwrap();
+
+        assert_eq!(
+            to_normalized_text_with(&transcript, 0, false, true, LabelFormat::Minutes),
+            "[00:10] Interviewer: so then\n[00:04] Guest: as I said\n[00:12] Interviewer: right\n"
+        );
+        assert_eq!(
+            to_normalized_text_with(&transcript, 0, false, false, LabelFormat::Minutes),
+            "[00:04] Guest: as I said\n[00:10] Interviewer: so then\n[00:12] Interviewer: right\n"
+        );
+    }
+
+    #[test]
+    fn unsorted_buckets_align_to_the_earliest_entry() {
+        // The second entry starts before the first, so the buckets are counted from 8s
+        let transcript: Vec<TranscriptItem> = [("a", 10.0), ("b", 8.0), ("c", 12.0)]
+            .into_iter()
+            .map(|(text, start)| TranscriptItem {
+                text: text.to_string(),
+                ..item_at(start)
+            })
+            .collect();
+        let starts = |items: Vec<TranscriptItem>| -> Vec<(String, f64)> {
+            items.into_iter().map(|item| (item.text, item.start)).collect()
+        };
+
+        assert_eq!(
+            starts(normalize_items_with(&transcript, 3, true, true)),
+            [("a b".to_string(), 8.0), ("c".to_string(), 11.0)]
+        );
+        assert_eq!(
+            starts(normalize_items_with(&transcript, 3, true, false)),
+            [("b a".to_string(), 8.0), ("c".to_string(), 11.0)]
+        );
+    }
+
+    #[test]
+    fn five_minutes_is_labelled_with_padded_minutes() {
+        let content = "[04:58] before\n[05:00] on the mark\n[05:03] after\n";
+        assert_eq!(
+            normalize_timestamps(content, 6),
+            "[04:54] before\n[05:00] on the mark after\n"
+        );
+        assert_eq!(
+            normalize_timestamps_with(content, 6, false, LabelFormat::Hours),
+            "[0:04:54] before\n[0:05:00] on the mark after\n"
+        );
+        assert_eq!(LabelFormat::Minutes.label(300.0), "[05:00]");
+        assert_eq!(LabelFormat::Minutes.label(3900.0), "[01:05:00]");
+    }
+
+    #[tokio::test]
+    async fn a_forbidden_page_reports_its_status_and_body() {
+        let server = MockServer::start();
+        server.mock(
+            "/watch?v=dQw4w9WgXcQ",
+            MockResponse::status(403, "<html>\n  Access denied: automated queries\n</html>"),
+        );
+
+        let error = fetch_video("dQw4w9WgXcQ", None, &options_for(&server)).await.unwrap_err();
+        let message = error.to_string();
+        assert!(matches!(error, TranscriptError::BadStatus { code: 403, .. }), "{:?}", error);
+        assert!(message.contains("403"), "{}", message);
+        assert!(message.contains("<html> Access denied: automated queries </html>"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn a_forbidden_caption_download_reports_its_status_and_body() {
+        let server = MockServer::start();
+        let player = crate::mock_server::player_response(server.url(), "dQw4w9WgXcQ", &["en"]);
+        server.mock("/watch?v=dQw4w9WgXcQ", MockResponse::ok(crate::mock_server::watch_page(&player)));
+        server.mock("/api/timedtext", MockResponse::status(403, "Signature expired"));
+
+        let error = fetch_video("dQw4w9WgXcQ", None, &options_for(&server)).await.unwrap_err();
+        assert_eq!(error.to_string(), "Request failed with HTTP status 403: Signature expired");
+    }
+
+    #[test]
+    fn one_malformed_cue_doesnt_lose_the_others() {
+        let xml = include_str!("../tests/fixtures/captions_malformed.xml");
+        let transcript = parse_caption_response(xml, None).unwrap();
+        let lines: Vec<(&str, f64, usize)> =
+            transcript.iter().map(|item| (item.text.as_str(), item.start, item.index)).collect();
+        assert_eq!(lines, [("first", 0.0, 0), ("second", 3.0, 1), ("third", 5.0, 2)]);
+
+        let nothing_parses = r#"<transcript><text start="x">a</text><text dur="1">b</text></transcript>"#;
+        assert!(matches!(
+            parse_caption_response(nothing_parses, None),
+            Err(TranscriptError::EmptyTranscript)
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_gzip_encoded_watch_page_is_decoded_before_parsing() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let server = MockServer::start();
+        let player = crate::mock_server::player_response(server.url(), "dQw4w9WgXcQ", &["en"]);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(crate::mock_server::watch_page(&player).as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        server.mock(
+            "/watch?v=dQw4w9WgXcQ",
+            MockResponse::ok(gzipped).header("Content-Encoding", "gzip"),
+        );
+        server.mock(
+            "/api/timedtext?v=dQw4w9WgXcQ&lang=en&fmt=json3",
+            MockResponse::ok(json3_body(&[(0, 1000, "unzipped")])),
+        );
+
+        let (metadata, transcript) = fetch_video("dQw4w9WgXcQ", None, &options_for(&server)).await.unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Mock video"));
+        assert_eq!(transcript[0].text, "unzipped");
+        let accepted = server.requests()[0].header("accept-encoding").unwrap_or_default().to_string();
+        assert!(accepted.contains("gzip") && accepted.contains("br"), "{}", accepted);
+    }
+
+    // The bucket loop normalize_timestamps_with had before it became a single
+    // pass: every bucket up to the last line scans all of the lines
+    fn normalize_by_scanning(content: &str, interval: i32, align_to_first: bool) -> String {
+        let mut entries: Vec<(f64, String)> = content.lines().filter_map(process_timestamp_line).collect();
+        entries.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut current = if align_to_first { entries[0].0.floor() as i32 } else { 0 };
+        let mut normalized = String::new();
+        while current <= entries.last().unwrap().0 as i32 {
+            let (start, end) = (current as f64, (current + interval) as f64);
+            let texts: Vec<&str> = entries
+                .iter()
+                .filter(|(ts, _)| *ts >= start && *ts < end)
+                .map(|(_, text)| text.as_str())
+                .collect();
+            let text = collapse_whitespace(&texts.join(" "));
+            if !text.is_empty() {
+                normalized.push_str(&format!("{} {}\n", LabelFormat::Minutes.label(current as f64), text));
+            }
+            current += interval;
+        }
+        normalized
+    }
+
+    #[test]
+    fn single_pass_normalizing_matches_scanning_every_bucket() {
+        // Irregular gaps, a few of them long, running past the first hour and a half
+        let mut seed = 7u64;
+        let mut secs = 3u64;
+        let mut content = String::new();
+        for i in 0..3000 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            secs += match seed >> 60 { 0 => 40, 1..=3 => 0, n => n % 5 };
+            content.push_str(&format!("{} line {}\n", format_seconds(secs as f64), i));
+        }
+        // and a few out of order
+        content.push_str("[00:05] late one\n[01:00:00] late two\n");
+
+        for interval in [1, 6, 7, 30, 600] {
+            for align in [false, true] {
+                assert_eq!(
+                    normalize_timestamps_with(&content, interval, align, LabelFormat::Minutes),
+                    normalize_by_scanning(&content, interval as i32, align),
+                    "interval {}, aligned {}",
+                    interval,
+                    align
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_saved_watch_page_lists_its_languages() {
+        let tracks = available_languages(include_str!("../tests/fixtures/watch_page_tracks.html")).unwrap();
+        let listed: Vec<(&str, Option<&str>, bool)> = tracks
+            .iter()
+            .map(|track| (track.language_code.as_str(), track.name.as_deref(), track.is_auto_generated))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("en", Some("English"), false),
+                ("en", Some("English (auto-generated)"), true),
+                ("pt-BR", Some("Portuguese (Brazil)"), false),
+            ]
+        );
+        assert!(matches!(
+            available_languages("<html><body>No player here</body></html>"),
+            Err(TranscriptError::PlayerDataNotFound)
+        ));
+    }
+}
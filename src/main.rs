@@ -1,221 +1,1985 @@
-use reqwest::Client;
+use clap::builder::RangedU64ValueParser;
+use clap::{Parser, Subcommand, ValueEnum};
+use futures::stream::{self, StreamExt};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, warn, LevelFilter};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use youtube_transcript::{
+    dedupe_rolling, fetch_channel_video_ids, fetch_playlist_video_ids, filter_range, find_gaps,
+    format_seconds, mark_speaker_turns, merge_into_sentences, normalize_punctuation,
+    normalize_timestamps_with, parse_channel_handle, parse_playlist_id, parse_timestamp,
+    parse_video_id, parse_video_link, sanitize_filename, search, strip_annotations,
+    to_chaptered_text_with, to_csv, to_json_with_metadata, to_jsonl, to_markdown,
+    to_normalized_text_with, to_plain_text, to_precise_text, to_srt, to_text_with_durations, to_vtt,
+    transcript_stats, wrap_lines, write_atomic, FetchOptions, LabelFormat, LangTranscript,
+    TranscriptFetcher, TranscriptItem, VideoMetadata, ANNOTATION_KEYWORDS, DEFAULT_INTERVAL_SECS,
+};
+
+#[cfg(test)]
+mod mock_server;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Plain text with normalized [MM:SS] timestamps
+    Txt,
+    /// SubRip subtitles
+    Srt,
+    /// WebVTT subtitles
+    Vtt,
+    /// JSON array of entries with the raw start/duration timings
+    Json,
+    /// JSON Lines, one compact object per entry
+    Jsonl,
+    /// CSV rows of start,duration,end,text
+    Csv,
+    /// Markdown list with timestamps linking to that moment of the video
+    Md,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum NameBy {
+    /// transcript_<VIDEO_ID>.<FORMAT>
+    Id,
+    /// <TITLE>_<VIDEO_ID>.<FORMAT>, falling back to the ID form without a title
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LabelStyle {
+    /// [MM:SS], switching to [HH:MM:SS] past the first hour
+    Minutes,
+    /// [H:MM:SS] on every line
+    Hours,
+}
+
+impl From<LabelStyle> for LabelFormat {
+    fn from(style: LabelStyle) -> Self {
+        match style {
+            LabelStyle::Minutes => LabelFormat::Minutes,
+            LabelStyle::Hours => LabelFormat::Hours,
+        }
+    }
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Txt => "txt",
+            OutputFormat::Srt => "srt",
+            OutputFormat::Vtt => "vtt",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Md => "md",
+        }
+    }
+}
+
+// The formats given to --format: one name, a comma-separated list like
+// "srt,vtt,json", or "all" for every format
+#[derive(Debug, Clone)]
+struct FormatList(Vec<OutputFormat>);
+
+// Plain text, when no format is given anywhere
+impl Default for FormatList {
+    fn default() -> Self {
+        FormatList(vec![OutputFormat::Txt])
+    }
+}
+
+fn parse_formats(value: &str) -> Result<FormatList, String> {
+    if value.eq_ignore_ascii_case("all") {
+        return Ok(FormatList(OutputFormat::value_variants().to_vec()));
+    }
+
+    let mut formats = Vec::new();
+    for name in value.split(',').map(str::trim) {
+        let format = OutputFormat::from_str(name, true).map_err(|_| {
+            format!("unknown format {:?}, expected txt, srt, vtt, json, jsonl, csv, md or all", name)
+        })?;
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+    Ok(FormatList(formats))
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Regroup an existing "[MM:SS] text" transcript file into time buckets
+    Normalize {
+        /// The transcript file to normalize
+        file: PathBuf,
+
+        /// Group lines into buckets of this many seconds (0 keeps every line)
+        #[arg(short, long, default_value_t = DEFAULT_INTERVAL_SECS)]
+        interval: u32,
+
+        /// Start the first bucket at the first line instead of at 0:00
+        #[arg(long)]
+        interval_align: bool,
+
+        /// Write the result to this file instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Download transcripts from YouTube videos", args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Video IDs or URLs; falls back to config.json when omitted
+    videos: Vec<String>,
+
+    /// Read additional video IDs or URLs from this file, one per line
+    #[arg(long)]
+    input_file: Option<PathBuf>,
+
+    /// Read additional video IDs or URLs from standard input, one per line
+    #[arg(long)]
+    stdin: bool,
+
+    /// How many videos to download at the same time
+    #[arg(long, default_value_t = 4)]
+    concurrency: usize,
+
+    /// Where to write the transcript (defaults to transcript_<VIDEO_ID>.<FORMAT>, "-" for stdout)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Directory for the automatically named output files, created if missing
+    #[arg(long, conflicts_with_all = ["output", "stdout"])]
+    output_dir: Option<PathBuf>,
+
+    /// Preferred caption language code, e.g. "en" or "es"
+    #[arg(short, long)]
+    lang: Option<String>,
+
+    /// Download every caption language the video has, one file per language
+    /// named with the language code (transcript_<VIDEO_ID>.<LANG>.<FORMAT>)
+    #[arg(long, conflicts_with = "lang")]
+    all_langs: bool,
+
+    /// Output format: txt (default), srt, vtt, json, jsonl, csv or md; several as a
+    /// comma-separated list, or "all". Defaults to YT_FORMAT or config.json when not given
+    #[arg(short, long, value_parser = parse_formats)]
+    format: Option<FormatList>,
+
+    /// Group plain text lines into buckets of this many seconds (0 keeps every line)
+    #[arg(short, long, default_value_t = DEFAULT_INTERVAL_SECS)]
+    interval: u32,
+
+    /// Start the first bucket at the first caption instead of at 0:00
+    #[arg(long)]
+    interval_align: bool,
+
+    /// How the timestamps of plain text lines are written
+    #[arg(long, value_enum, default_value_t = LabelStyle::Minutes)]
+    label_format: LabelStyle,
+
+    /// Only keep captions starting at or after this time (seconds, MM:SS or HH:MM:SS)
+    #[arg(long, value_parser = parse_time_arg)]
+    start: Option<f64>,
+
+    /// Only keep captions starting before this time (seconds, MM:SS or HH:MM:SS)
+    #[arg(long, value_parser = parse_time_arg)]
+    end: Option<f64>,
+
+    /// Only keep the first N captions (after --start / --end), for a quick preview
+    #[arg(long, value_name = "N", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    limit: Option<usize>,
+
+    /// Remove non-speech annotations like [Music] or (laughs) from the captions
+    #[arg(long)]
+    strip_annotations: bool,
+
+    /// Remove the words rolling auto captions repeat from the previous line
+    #[arg(long)]
+    dedupe: bool,
+
+    /// Replace curly quotes, dashes and non-breaking spaces with plain ASCII
+    #[arg(long, visible_alias = "normalize-unicode")]
+    ascii: bool,
+
+    /// Also treat "Name:" labels at the start of a caption as speaker turns,
+    /// not just ">>" markers, so each turn starts a line of its own
+    #[arg(long)]
+    speakers: bool,
+
+    /// Merge caption fragments into whole sentences before writing
+    #[arg(long)]
+    sentences: bool,
+
+    /// Write plain text with millisecond timestamps ([MM:SS.mmm]), one line per entry
+    #[arg(long)]
+    precise: bool,
+
+    /// Split plain text into sections headed by the video's chapters, when it has any
+    #[arg(long)]
+    chapters: bool,
+
+    /// Wrap plain text lines at this many columns, indenting continuation lines
+    #[arg(long, value_name = "COLS", value_parser = clap::value_parser!(u16).range(1..))]
+    wrap: Option<u16>,
+
+    /// Write plain text one line per caption with its duration ([MM:SS +3.2s])
+    #[arg(long)]
+    show_duration: bool,
+
+    /// Keep the captions in their original order instead of sorting them by time
+    #[arg(long)]
+    no_sort: bool,
+
+    /// Write plain text as running prose without any timestamps
+    #[arg(long)]
+    no_timestamps: bool,
+
+    /// Write plain text as one flowing paragraph of whole sentences, for
+    /// summarizers (the same as --sentences with --no-timestamps)
+    #[arg(long, conflicts_with_all = ["precise", "chapters", "show_duration"])]
+    prose: bool,
+
+    /// Leave output files that already exist untouched instead of overwriting them
+    #[arg(long, conflicts_with = "overwrite")]
+    skip_existing: bool,
+
+    /// Add each transcript to the end of --output, under a heading naming the video
+    #[arg(long, requires = "output", conflicts_with_all = ["skip_existing", "overwrite", "stdout"])]
+    append: bool,
+
+    /// Overwrite output files that already exist (the default)
+    #[arg(long)]
+    overwrite: bool,
+
+    /// How to name the output file when --output isn't given
+    #[arg(long, value_enum, default_value_t = NameBy::Id)]
+    name_by: NameBy,
+
+    /// List the available caption tracks and exit without downloading
+    #[arg(long)]
+    list_langs: bool,
+
+    /// Only check that captions (in --lang, if given) exist, without downloading
+    /// them; exits with status 1 unless every video has them
+    #[arg(long, conflicts_with_all = ["list_langs", "words", "raw_xml"])]
+    check: bool,
+
+    /// Print every word with its own start time ([MM:SS.mmm] word) instead of saving a transcript
+    #[arg(long, conflicts_with = "list_langs")]
+    words: bool,
+
+    /// Save the caption XML exactly as YouTube sent it (transcript_<VIDEO_ID>.xml
+    /// or --output) instead of a parsed transcript
+    #[arg(long, conflicts_with_all = ["list_langs", "words", "append"])]
+    raw_xml: bool,
+
+    /// Download every video in this playlist (ID or URL), one file per video
+    #[arg(long, conflicts_with_all = ["videos", "input_file", "stdin", "output"])]
+    playlist: Option<String>,
+
+    /// Send the page requests to this server instead of https://www.youtube.com
+    #[arg(long, hide = true)]
+    base_url: Option<String>,
+
+    /// Download the latest uploads of this channel (@handle or URL), one file per video
+    #[arg(
+        long,
+        value_name = "HANDLE",
+        conflicts_with_all = ["videos", "input_file", "stdin", "output", "playlist"]
+    )]
+    channel: Option<String>,
+
+    /// Only take this many of the channel's latest uploads
+    #[arg(long, value_name = "N", requires = "channel")]
+    latest: Option<usize>,
+
+    /// Retry failed requests this many times, backing off exponentially
+    #[arg(long, default_value_t = FetchOptions::default().retries)]
+    retries: u32,
+
+    /// Refuse watch pages and other pages larger than this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = FetchOptions::default().max_page_size)]
+    max_page_size: usize,
+
+    /// Refuse caption tracks larger than this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = FetchOptions::default().max_caption_size)]
+    max_caption_size: usize,
+
+    /// Drop auto-generated caption segments less confident than this (0 to 1)
+    #[arg(long, value_name = "CONFIDENCE", value_parser = parse_confidence)]
+    min_confidence: Option<f64>,
+
+    /// Machine-translate the captions into this language code, e.g. "fr"
+    #[arg(long)]
+    translate: Option<String>,
+
+    /// Give up on a request after this many seconds
+    #[arg(long, default_value_t = FetchOptions::default().timeout.as_secs())]
+    timeout: u64,
+
+    /// HTTP or SOCKS5 proxy URL (defaults to the HTTP_PROXY / HTTPS_PROXY environment variables)
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// User-Agent header to send with every request
+    #[arg(long)]
+    user_agent: Option<String>,
+
+    /// Get the caption data from YouTube's player API instead of the watch page
+    #[arg(long)]
+    api: bool,
+
+    /// Netscape-format cookies.txt to send with requests (for age-restricted videos)
+    #[arg(long)]
+    cookies: Option<PathBuf>,
+
+    /// Directory for cached transcripts (defaults to ~/.cache/youtube_transcript)
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// Seconds a cached transcript is reused before fetching it again
+    #[arg(long, default_value_t = FetchOptions::default().cache_ttl.as_secs())]
+    cache_ttl: u64,
+
+    /// Always fetch from YouTube, neither reading nor writing the cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Write the transcript to stdout instead of a file (same as --output -)
+    #[arg(long, conflicts_with = "output")]
+    stdout: bool,
+
+    /// Print only the lines containing this term instead of saving the transcript
+    #[arg(long)]
+    grep: Option<String>,
+
+    /// Match the --grep term regardless of case
+    #[arg(long, requires = "grep")]
+    ignore_case: bool,
+
+    /// Print the silent stretches longer than this many seconds instead of saving
+    #[arg(long, value_name = "SECS")]
+    gaps: Option<f64>,
+
+    /// Print word count, spoken duration and words per minute after fetching
+    #[arg(long)]
+    stats: bool,
+
+    /// Print the videos and output files a run would produce, without downloading or writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Only log warnings and errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log every step, including debug details
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+// Parse a --start/--end value
+fn parse_time_arg(value: &str) -> Result<f64, String> {
+    parse_timestamp(value).ok_or_else(|| format!("invalid time {:?}, expected seconds, MM:SS or HH:MM:SS", value))
+}
+
+fn parse_confidence(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(confidence) if (0.0..=1.0).contains(&confidence) => Ok(confidence),
+        _ => Err(format!("invalid confidence {:?}, expected a number from 0 to 1", value)),
+    }
+}
+
+impl Cli {
+    // Log level picked by --quiet / --verbose; RUST_LOG still overrides it
+    fn log_level(&self) -> LevelFilter {
+        if self.quiet {
+            LevelFilter::Warn
+        } else if self.verbose {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        }
+    }
+
+    // --prose turns on the two flags it stands for
+    fn expand_prose(&mut self) {
+        if self.prose {
+            self.sentences = true;
+            self.no_timestamps = true;
+        }
+    }
+
+    fn writes_to_stdout(&self) -> bool {
+        self.stdout || self.output.as_deref() == Some(Path::new("-"))
+    }
+
+    // Where the transcript in this format is written: --output when given (with
+    // the format's extension when several formats are written), otherwise the
+    // name picked by --name-by
+    // With --all-langs the language code is added before the extension
+    fn output_path(
+        &self,
+        formats: &[OutputFormat],
+        video_id: &str,
+        lang: Option<&str>,
+        metadata: &VideoMetadata,
+        format: OutputFormat,
+    ) -> PathBuf {
+        let path = self.output_path_for_video(formats, video_id, metadata, format);
+        match lang.filter(|_| self.all_langs) {
+            Some(lang) => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                path.with_file_name(format!("{}.{}.{}", stem, lang, format.extension()))
+            }
+            None => path,
+        }
+    }
+
+    fn output_path_for_video(
+        &self,
+        formats: &[OutputFormat],
+        video_id: &str,
+        metadata: &VideoMetadata,
+        format: OutputFormat,
+    ) -> PathBuf {
+        match &self.output {
+            Some(output) if formats.len() > 1 => output.with_extension(format.extension()),
+            Some(output) => output.clone(),
+            None => {
+                let name = default_output_path(video_id, metadata, format, self.name_by);
+                match &self.output_dir {
+                    Some(dir) => dir.join(name),
+                    None => name,
+                }
+            }
+        }
+    }
+
+    // Whether --skip-existing leaves this output file alone; never when writing to stdout
+    fn keeps_existing(&self, output: &Path) -> bool {
+        let keep = self.skip_existing && !self.writes_to_stdout() && output.exists();
+        if keep {
+            info!("Skipping {}, it already exists", output.display());
+        }
+        keep
+    }
+
+    // A fetcher configured from the network and caption flags
+    // Without a range the first --limit captions are the first ones of the track,
+    // so the download can stop there; with one they're only known once it's cut
+    fn limited_fetcher(
+        &self,
+        session: &TranscriptFetcher,
+        lang: Option<&str>,
+        start: Option<f64>,
+    ) -> TranscriptFetcher {
+        let fetcher = self.fetcher(session, lang);
+        match self.limit {
+            Some(limit) if start.is_none() && self.end.is_none() => fetcher.limit(limit),
+            _ => fetcher,
+        }
+    }
+
+    // Built from the run's session, so every fetcher shares the caption tracks
+    // already downloaded
+    fn fetcher(&self, session: &TranscriptFetcher, lang: Option<&str>) -> TranscriptFetcher {
+        let mut fetcher = session
+            .clone()
+            .retries(self.retries)
+            .max_page_size(self.max_page_size)
+            .max_caption_size(self.max_caption_size)
+            .timeout(Duration::from_secs(self.timeout))
+            .cache_ttl(Duration::from_secs(self.cache_ttl));
+        if let Some(lang) = lang {
+            fetcher = fetcher.lang(lang);
+        }
+        if let Some(proxy) = &self.proxy {
+            fetcher = fetcher.proxy(proxy);
+        }
+        if let Some(cookies) = &self.cookies {
+            fetcher = fetcher.cookies(cookies);
+        }
+        let cache_dir = self.cache_dir.clone().or_else(default_cache_dir);
+        if let Some(dir) = cache_dir.filter(|_| !self.no_cache) {
+            fetcher = fetcher.cache_dir(dir);
+        }
+        if let Some(target) = &self.translate {
+            fetcher = fetcher.translate(target);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            fetcher = fetcher.user_agent(user_agent);
+        }
+        if let Some(min_confidence) = self.min_confidence {
+            fetcher = fetcher.min_confidence(min_confidence);
+        }
+        if let Some(base_url) = &self.base_url {
+            fetcher = fetcher.base_url(base_url);
+        }
+        fetcher.use_api(self.api)
+    }
+}
+
+// Read from the working directory, see read_config
+const CONFIG_PATH: &str = "config.json";
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Config {
-    video_id: String,
+    #[serde(default)]
+    video_url: Option<String>,
+    #[serde(default)]
+    video_id: Option<String>,
+    #[serde(default)]
+    lang: Option<String>,
+    #[serde(default)]
+    format: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TranscriptItem {
-    text: String,
-    start: f64,
-    duration: f64,
-}
-
-impl TranscriptItem {
-    // This method formats the timestamp of a transcript item into a readable string
-    // It takes the start time in seconds and converts it to [MM:SS] format
-    // For example: 
-    // - If start time is 65.0 seconds, returns "[01:05]"
-    // - If start time is 125.5 seconds, returns "[02:05]"
-    fn format_time(&self) -> String {
-        let start_mins = (self.start / 60.0).floor(); // Convert seconds to minutes
-        let start_secs = (self.start % 60.0).floor(); // Get remaining seconds
-        format!("[{:02}:{:02}]", start_mins, start_secs) // Format as [MM:SS]
-    }
-}
-
-fn extract_json(html: &str) -> Option<&str> {
-    let start_marker = "ytInitialPlayerResponse = ";
-    let end_marker = ";</script>";
-
-    html.find(start_marker)
-        .map(|start_idx| {
-            let start_pos = start_idx + start_marker.len();
-            let sub_str = &html[start_pos..];
-            let end_pos = sub_str.find(end_marker).unwrap_or(sub_str.len());
-            &sub_str[..end_pos]
-        })
+impl Config {
+    // Either field may hold a bare ID or a full link, so run whichever is
+    // present through parse_video_id (the ID field wins if both are set)
+    fn resolve_video_id(&self) -> Option<String> {
+        self.video_id
+            .as_deref()
+            .or(self.video_url.as_deref())
+            .and_then(parse_video_id)
+    }
 }
 
-fn build_youtube_url(video_id: &str) -> String {
-    format!("https://www.youtube.com/watch?v={}", video_id)
+// $XDG_CACHE_HOME/youtube_transcript, or ~/.cache/youtube_transcript
+fn default_cache_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(base.join("youtube_transcript"))
 }
 
-async fn get_transcript(video_id: &str) -> Result<Vec<TranscriptItem>, Box<dyn Error>> {
-    let client = Client::builder()
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-        .build()?;
+fn default_output_path(
+    video_id: &str,
+    metadata: &VideoMetadata,
+    format: OutputFormat,
+    name_by: NameBy,
+) -> PathBuf {
+    let title = match name_by {
+        NameBy::Title => metadata.title.as_deref().map(sanitize_filename),
+        NameBy::Id => None,
+    };
+    match title {
+        // Keep the ID as a suffix so two videos with the same title don't collide
+        Some(title) if !title.is_empty() => {
+            PathBuf::from(format!("{}_{}.{}", title, video_id, format.extension()))
+        }
+        _ => PathBuf::from(format!("transcript_{}.{}", video_id, format.extension())),
+    }
+}
 
-    let url = build_youtube_url(video_id);
-    println!("Fetching video page...");
-    
-    let response = client
-        .get(&url)
-        .send()
-        .await?;
+// Print a short header describing the video, skipping anything YouTube didn't provide
+fn print_metadata(metadata: &VideoMetadata) {
+    if let Some(title) = &metadata.title {
+        info!("Title: {}", title);
+    }
+    if let Some(author) = &metadata.author {
+        info!("Author: {}", author);
+    }
+    if let Some(length) = metadata.length_seconds {
+        info!("Length: {}:{:02}", length / 60, length % 60);
+    }
+    if let Some(views) = metadata.view_count {
+        info!("Views: {}", views);
+    }
+}
 
-    let html = response.text().await?;
+// Render the transcript in one of the formats chosen on the command line
+fn render(
+    cli: &Cli,
+    format: OutputFormat,
+    video_id: &str,
+    metadata: &VideoMetadata,
+    transcript: &[TranscriptItem],
+) -> Result<String, Box<dyn Error>> {
+    let content = render_unwrapped(cli, format, video_id, metadata, transcript)?;
+    Ok(match cli.wrap {
+        Some(width) if format == OutputFormat::Txt => wrap_lines(&content, width.into()),
+        _ => content,
+    })
+}
 
-    println!("Extracting caption data...");
-    let json_str = extract_json(&html).ok_or("Cannot find player data")?;
+fn render_unwrapped(
+    cli: &Cli,
+    format: OutputFormat,
+    video_id: &str,
+    metadata: &VideoMetadata,
+    transcript: &[TranscriptItem],
+) -> Result<String, Box<dyn Error>> {
+    Ok(match format {
+        OutputFormat::Txt if cli.no_timestamps => to_plain_text(transcript),
+        OutputFormat::Txt if cli.precise => to_precise_text(transcript),
+        OutputFormat::Txt if cli.show_duration => to_text_with_durations(transcript),
+        OutputFormat::Txt if cli.chapters => to_chaptered_text_with(
+            transcript,
+            &metadata.chapters,
+            cli.interval,
+            cli.interval_align,
+            cli.no_sort,
+            cli.label_format.into(),
+        ),
+        OutputFormat::Txt => to_normalized_text_with(
+            transcript,
+            cli.interval,
+            cli.interval_align,
+            cli.no_sort,
+            cli.label_format.into(),
+        ),
+        OutputFormat::Srt => to_srt(transcript),
+        OutputFormat::Vtt => to_vtt(transcript),
+        OutputFormat::Json => to_json_with_metadata(metadata, transcript)?,
+        OutputFormat::Jsonl => to_jsonl(transcript)?,
+        OutputFormat::Csv => to_csv(transcript),
+        OutputFormat::Md => to_markdown(transcript, video_id),
+    })
+}
+
+// Write the transcript in every chosen format to `out`, one after the other
+fn write_formats(
+    cli: &Cli,
+    formats: &[OutputFormat],
+    out: &mut impl Write,
+    video_id: &str,
+    metadata: &VideoMetadata,
+    transcript: &[TranscriptItem],
+) -> Result<(), Box<dyn Error>> {
+    for &format in formats {
+        out.write_all(render(cli, format, video_id, metadata, transcript)?.as_bytes())?;
+    }
+    Ok(())
+}
+
+// Create the --output-dir, parents included, unless it's only a dry run
+fn create_output_dir(cli: &Cli) -> io::Result<()> {
+    match cli.output_dir.as_ref().filter(|_| !cli.dry_run) {
+        Some(dir) => fs::create_dir_all(dir),
+        None => Ok(()),
+    }
+}
+
+// Held while appending, so videos of a batch never interleave in the shared file
+static APPEND_LOCK: Mutex<()> = Mutex::new(());
+
+// Add a rendered transcript to the end of `output` (creating it if needed),
+// headed by a "# Title (VIDEO_ID)" line and followed by a blank line
+fn append_transcript(
+    output: &Path,
+    video_id: &str,
+    metadata: &VideoMetadata,
+    content: &str,
+) -> io::Result<()> {
+    let heading = match &metadata.title {
+        Some(title) => format!("# {} ({})\n", title, video_id),
+        None => format!("# {}\n", video_id),
+    };
+
+    let _guard = APPEND_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(output)?;
+    file.write_all(format!("{}{}\n", heading, content).as_bytes())
+}
+
+// An environment variable's value, treating an empty one as unset
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+// config.json in the working directory, None when there isn't one
+fn read_config() -> Result<Option<Config>, Box<dyn Error>> {
+    match fs::read_to_string(CONFIG_PATH) {
+        Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read {}: {}", CONFIG_PATH, e).into()),
+    }
+}
+
+// The output formats from --format, then YT_FORMAT, then the config file's
+// format field, falling back to plain text. YT_FORMAT is only looked at (and
+// only has to be valid) when --format isn't given
+fn resolve_format(cli: &Cli, config: Option<&Config>) -> Result<Vec<OutputFormat>, String> {
+    if let Some(formats) = &cli.format {
+        return Ok(formats.0.clone());
+    }
+    let formats = if let Some(value) = env_var("YT_FORMAT") {
+        parse_formats(&value).map_err(|e| format!("YT_FORMAT: {}", e))?
+    } else {
+        match config.and_then(|config| config.format.as_deref()) {
+            Some(value) => parse_formats(value).map_err(|e| format!("{}: {}", CONFIG_PATH, e))?,
+            None => FormatList::default(),
+        }
+    };
+    Ok(formats.0)
+}
+
+// --lang, or YT_LANG when it isn't given
+fn requested_lang(cli: &Cli) -> Option<String> {
+    cli.lang.clone().or_else(|| env_var("YT_LANG"))
+}
+
+// Read one video ID or URL per line, skipping blank lines and # comments
+fn read_video_list(reader: impl BufRead) -> io::Result<Vec<String>> {
+    let mut inputs = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            inputs.push(line.to_string());
+        }
+    }
+    Ok(inputs)
+}
+
+// Download one video's transcript and save it in the requested format, either
+// to a file or straight to stdout
+// `link_start` is the offset a link's t= parameter asked for, used when --start isn't given
+async fn process_video(
+    cli: &Cli,
+    session: &TranscriptFetcher,
+    formats: &[OutputFormat],
+    video_id: &str,
+    lang: Option<&str>,
+    link_start: Option<f64>,
+) -> Result<(), Box<dyn Error>> {
+    // Unless the files are named after the title, their names are known before
+    // anything is downloaded, so existing ones can be skipped without a request
+    if cli.skip_existing && !cli.writes_to_stdout() && (cli.output.is_some() || cli.name_by == NameBy::Id) {
+        let no_metadata = VideoMetadata::default();
+        let all_exist = formats.iter().all(|&format| {
+            cli.output_path(formats, video_id, lang, &no_metadata, format).exists()
+        });
+        if all_exist {
+            info!("Skipping {}, its output already exists", video_id);
+            return Ok(());
+        }
+    }
+
+    info!("Starting transcript download for video ID: {}", video_id);
     
-    let parsed: serde_json::Value = serde_json::from_str(json_str)?;
-
-    if let Some(captions) = parsed
-        .get("captions")
-        .and_then(|c| c.get("playerCaptionsTracklistRenderer"))
-        .and_then(|p| p.get("captionTracks"))
-        .and_then(|t| t.as_array())
-    {
-        println!("Found caption tracks...");
-        if let Some(first_track) = captions.first() {
-            if let Some(base_url) = first_track.get("baseUrl").and_then(|u| u.as_str()) {
-                println!("Downloading transcript...");
-                let transcript_response = client.get(base_url).send().await?;
-                let transcript_xml = transcript_response.text().await?;
-
-                println!("Parsing transcript data...");
-                let re = regex::Regex::new(r#"<text start="([^"]+)" dur="([^"]+)"[^>]*>([^<]+)</text>"#)?;
-                let mut transcript = Vec::new();
-
-                for cap in re.captures_iter(&transcript_xml) {
-                    let start: f64 = cap[1].parse()?;
-                    let duration: f64 = cap[2].parse()?;
-                    let text = html_escape::decode_html_entities(&cap[3]).into_owned();
-
-                    transcript.push(TranscriptItem {
-                        text,
-                        start,
-                        duration,
-                    });
-                }
+    let start = cli.start.or(link_start);
+    let fetcher = cli.limited_fetcher(session, lang, start);
+    let (metadata, transcript) = fetcher.fetch_video(video_id).await?;
+    save_video(cli, formats, video_id, lang, start, &metadata, transcript)
+}
 
-                if transcript.is_empty() {
-                    return Err("No transcript lines found in the response".into());
-                }
+// Cut a fetched transcript to the requested range, apply the transformations
+// asked for and save it in every requested format (or print what --grep, --gaps
+// or --stats asked for)
+fn save_video(
+    cli: &Cli,
+    formats: &[OutputFormat],
+    video_id: &str,
+    lang: Option<&str>,
+    start: Option<f64>,
+    metadata: &VideoMetadata,
+    mut transcript: Vec<TranscriptItem>,
+) -> Result<(), Box<dyn Error>> {
+    if start.is_some() || cli.end.is_some() {
+        transcript = filter_range(&transcript, start, cli.end)?;
+        transcript.truncate(cli.limit.unwrap_or(usize::MAX));
+    }
+    if cli.strip_annotations {
+        transcript = strip_annotations(&transcript, ANNOTATION_KEYWORDS);
+    }
+    if cli.ascii {
+        transcript = normalize_punctuation(&transcript);
+    }
+    if cli.speakers {
+        transcript = mark_speaker_turns(&transcript);
+    }
+    if cli.dedupe {
+        transcript = dedupe_rolling(&transcript);
+    }
+    if cli.sentences {
+        transcript = merge_into_sentences(&transcript);
+    }
+    print_metadata(metadata);
+    info!("Transcript found! ({} lines)", transcript.len());
+
+    if let Some(term) = &cli.grep {
+        for item in search(&transcript, term, cli.ignore_case) {
+            println!("{} {}", item.format_time(), item.text);
+        }
+        return Ok(());
+    }
 
-                println!("Successfully parsed {} lines", transcript.len());
-                return Ok(transcript);
+    if let Some(min_gap) = cli.gaps {
+        for (start, end) in find_gaps(&transcript, min_gap) {
+            println!("{} - {} ({:.1}s)", format_seconds(start), format_seconds(end), end - start);
+        }
+        return Ok(());
+    }
+
+    if cli.stats {
+        let stats = transcript_stats(&transcript);
+        let secs = stats.total_duration.round() as u64;
+        let summary = format!(
+            "Words: {}\nDuration: {}:{:02}\nWords per minute: {:.1}",
+            stats.word_count,
+            secs / 60,
+            secs % 60,
+            stats.words_per_minute
+        );
+        // Keep piped transcript data on stdout clean
+        if cli.writes_to_stdout() {
+            eprintln!("{}", summary);
+        } else {
+            println!("{}", summary);
+        }
+    }
+
+    // Every format is rendered from the same fetched transcript
+    if cli.writes_to_stdout() {
+        write_formats(cli, formats, &mut io::stdout().lock(), video_id, metadata, &transcript)?;
+        return Ok(());
+    }
+
+    for &format in formats {
+        let output = cli.output_path(formats, video_id, lang, metadata, format);
+        if cli.append {
+            let content = render(cli, format, video_id, metadata, &transcript)?;
+            append_transcript(&output, video_id, metadata, &content)?;
+            info!("Transcript appended to {}", output.display());
+            continue;
+        }
+        if cli.keeps_existing(&output) {
+            continue;
+        }
+        write_atomic(&output, render(cli, format, video_id, metadata, &transcript)?)?;
+        info!("Transcript saved to {}", output.display());
+    }
+
+    Ok(())
+}
+
+// Same as process_video, but with --all-langs every caption language of the
+// video is downloaded in turn. A language that fails is reported and skipped;
+// only when none of them worked is the video counted as failed
+async fn process_video_langs(
+    cli: &Cli,
+    session: &TranscriptFetcher,
+    formats: &[OutputFormat],
+    video_id: &str,
+    lang: Option<&str>,
+    link_start: Option<f64>,
+) -> Result<(), Box<dyn Error>> {
+    if !cli.all_langs {
+        return process_video(cli, session, formats, video_id, lang, link_start).await;
+    }
+
+    info!("Starting transcript download for every language of video ID: {}", video_id);
+    let start = cli.start.or(link_start);
+    let fetcher = cli.limited_fetcher(session, None, start);
+    let (metadata, transcripts) = fetcher.fetch_all_langs(video_id).await?;
+    let codes: Vec<&str> = transcripts.iter().map(|t| t.lang.as_str()).collect();
+    info!("Found {} caption languages for {}: {}", codes.len(), video_id, codes.join(", "));
+
+    let count = transcripts.len();
+    let mut failed = 0;
+    for LangTranscript { lang, transcript } in transcripts {
+        let result = match transcript {
+            Ok(transcript) => {
+                save_video(cli, formats, video_id, Some(&lang), start, &metadata, transcript)
             }
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            warn!("Skipping the {} captions of {}: {}", lang, video_id, e);
+            failed += 1;
+        }
+    }
+    if failed > 0 && failed == count {
+        return Err(format!("None of the {} caption languages could be downloaded", failed).into());
+    }
+    Ok(())
+}
+
+// Print which videos would be downloaded and where each would be written,
+// without fetching any captions or writing anything
+// Files named after the title can't be known without fetching the video, so
+// their ID-based fallback name is shown instead
+fn print_plan(
+    cli: &Cli,
+    formats: &[OutputFormat],
+    video_ids: &[String],
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let no_metadata = VideoMetadata::default();
+    let mut planned: Vec<PathBuf> = Vec::new();
+
+    for video_id in video_ids {
+        if cli.writes_to_stdout() {
+            writeln!(out, "{} -> stdout", video_id)?;
+            continue;
+        }
+        for &format in formats {
+            let output = cli.output_path(formats, video_id, None, &no_metadata, format);
+            let note = if cli.append {
+                " (appended)"
+            } else if planned.contains(&output) {
+                " (collides with another video)"
+            } else if output.exists() && cli.skip_existing {
+                " (exists, would be skipped)"
+            } else if output.exists() {
+                " (exists, would be overwritten)"
+            } else {
+                ""
+            };
+            writeln!(out, "{} -> {}{}", video_id, output.display(), note)?;
+            planned.push(output);
         }
     }
 
-    Err("No captions found for this video".into())
+    if cli.name_by == NameBy::Title && cli.output.is_none() && !cli.writes_to_stdout() {
+        info!("Files will be named after the video titles, which are only known once fetched");
+    }
+    info!("{} videos would be downloaded", video_ids.len());
+    Ok(())
+}
+
+// The progress bar of the batch being downloaded, if any
+static ACTIVE_PROGRESS: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+// env_logger, but writing every message with the active progress bar suspended,
+// so log lines show up above the bar instead of tearing through it
+struct ProgressLogger(env_logger::Logger);
+
+impl log::Log for ProgressLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.0.matches(record) {
+            return;
+        }
+        let progress = ACTIVE_PROGRESS.lock().unwrap().clone();
+        match progress {
+            Some(progress) => progress.suspend(|| self.0.log(record)),
+            None => self.0.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
 }
 
-fn save_transcript(transcript: &[TranscriptItem], video_id: &str) -> Result<(), Box<dyn Error>> {
-    // First convert TranscriptItems to the format we need
-    let content = transcript.iter()
-        .map(|item| format!("{} {}", item.format_time(), item.text))
-        .collect::<Vec<_>>()
-        .join("\n");
+// Progress goes through the logger, which writes to stderr, so stdout only ever carries data
+fn init_logging(cli: &Cli) -> Result<(), log::SetLoggerError> {
+    let logger = env_logger::Builder::new()
+        .filter_level(cli.log_level())
+        .parse_env("RUST_LOG")
+        .format_timestamp(None)
+        .format_target(false)
+        .build();
+    log::set_max_level(logger.filter());
+    log::set_logger(Box::leak(Box::new(ProgressLogger(logger))))
+}
+
+fn batch_progress_bar(cli: &Cli, total: u64) -> ProgressBar {
+    if cli.quiet {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(total);
+    bar.set_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} videos ({eta} left)")
+            .expect("progress bar template is valid"),
+    );
+    bar
+}
+
+// Download the videos, starting no new one once `interrupted` is set, and count
+// the ones that were started and the ones of those that failed
+async fn download_videos(
+    cli: &Cli,
+    session: &TranscriptFetcher,
+    formats: &[OutputFormat],
+    videos: Vec<(String, Option<f64>)>,
+    lang: Option<&str>,
+    interrupted: &AtomicBool,
+    progress: &ProgressBar,
+) -> (usize, usize) {
+    stream::iter(videos)
+        .take_while(|_| futures::future::ready(!interrupted.load(Ordering::SeqCst)))
+        .map(|(video_id, link_start)| async move {
+            let result =
+                process_video_langs(cli, session, formats, &video_id, lang, link_start).await;
+            if let Err(e) = &result {
+                progress.suspend(|| eprintln!("Skipping {}: {}", video_id, e));
+            }
+            progress.inc(1);
+            result.is_err()
+        })
+        .buffer_unordered(cli.concurrency.max(1))
+        .fold((0, 0), |(done, failed), is_err| {
+            futures::future::ready((done + 1, failed + usize::from(is_err)))
+        })
+        .await
+}
+
+// Download several videos, up to --concurrency of them at a time
+// Each video is written on its own; one failing is reported and the rest carry on
+// A progress bar over the videos is drawn on stderr, unless --quiet is given
+// (or stderr isn't a terminal, in which case indicatif hides it by itself)
+// Ctrl-C stops new videos from being started while the ones in flight finish,
+// so no file is left half-written; a second Ctrl-C exits right away
+async fn process_batch(
+    cli: &Cli,
+    session: &TranscriptFetcher,
+    formats: &[OutputFormat],
+    videos: Vec<(String, Option<f64>)>,
+    lang: Option<&str>,
+) {
+    let total = videos.len();
+    let progress = batch_progress_bar(cli, total as u64);
+    *ACTIVE_PROGRESS.lock().unwrap() = Some(progress.clone());
+    let progress = &progress;
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let signal_handler = tokio::spawn({
+        let interrupted = Arc::clone(&interrupted);
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                interrupted.store(true, Ordering::SeqCst);
+                warn!("Interrupted, finishing the downloads in progress (Ctrl-C again to quit now)...");
+            }
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    });
+
+    let (done, failed) =
+        download_videos(cli, session, formats, videos, lang, &interrupted, progress).await;
+    signal_handler.abort();
+    progress.finish_and_clear();
+    *ACTIVE_PROGRESS.lock().unwrap() = None;
 
-    // Normalize the timestamps
-    let normalized = normalize_timestamps(&content);
+    info!("Downloaded {} of {} transcripts", done - failed, total);
+    if done < total {
+        warn!("Stopped early, {} videos were not started", total - done);
+    }
+}
 
-    // Save the normalized version
-    fs::write(format!("transcript_{}.txt", video_id), normalized)?;
+// Save the caption XML of each video byte for byte, to transcript_{id}.xml,
+// the --output path or standard output
+async fn save_raw_captions(
+    cli: &Cli,
+    session: &TranscriptFetcher,
+    video_ids: &[String],
+    lang: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    for video_id in video_ids {
+        let xml = cli.fetcher(session, lang).raw_captions(video_id).await?;
+        if cli.writes_to_stdout() {
+            io::stdout().write_all(&xml)?;
+            continue;
+        }
+        let output = match &cli.output {
+            Some(output) => output.clone(),
+            None => {
+                let name = PathBuf::from(format!("transcript_{}.xml", video_id));
+                cli.output_dir.as_ref().map_or(name.clone(), |dir| dir.join(&name))
+            }
+        };
+        if cli.keeps_existing(&output) {
+            continue;
+        }
+        write_atomic(&output, xml)?;
+        info!("Raw captions saved to {}", output.display());
+    }
     Ok(())
 }
 
-fn process_timestamp_line(line: &str) -> Option<(f64, String)> {
-    if let Some(timestamp_end) = line.find(']') {
-        if line.starts_with('[') {
-            let timestamp_str = &line[1..timestamp_end];
-            let text = line[timestamp_end + 1..].trim().to_string();
-            
-            // Convert timestamp to seconds
-            if let Some((minutes, seconds)) = timestamp_str.split_once(':') {
-                if let (Ok(min), Ok(sec)) = (minutes.parse::<f64>(), seconds.parse::<f64>()) {
-                    return Some((min * 60.0 + sec, text));
+// Run a subcommand, writing to `out` whatever doesn't go to a file
+fn run_command(command: &Command, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Normalize {
+            file,
+            interval,
+            interval_align,
+            output,
+        } => {
+            let content = fs::read_to_string(file)?;
+            let normalized =
+                normalize_timestamps_with(&content, *interval, *interval_align, LabelFormat::default());
+            match output {
+                Some(path) => {
+                    write_atomic(path, normalized)?;
+                    info!("Normalized transcript saved to {}", path.display());
                 }
+                None => out.write_all(normalized.as_bytes())?,
             }
         }
     }
-    None
+    Ok(())
 }
 
-// Normalize timestamps
-#[allow(unused_mut)]
-fn normalize_timestamps(content: &str) -> String {
-    let mut normalized = String::new();
-    let mut current_timestamp = 0;
-    let interval = 6; // 6-second intervals
-    
-    // Process each line and collect timestamps and text
-    let mut entries: Vec<(f64, String)> = content
-        .lines()
-        .filter_map(process_timestamp_line)
-        .collect();
-    
-    // Sort by timestamp if needed
-    entries.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-    
-    // Group text into 6-second intervals
-    while current_timestamp <= (entries.last().map(|e| e.0).unwrap_or(0.0) as i32) {
-        let start_time = current_timestamp as f64;
-        let end_time = (current_timestamp + interval) as f64;
-        
-        let text: String = entries
-            .iter()
-            .filter(|(ts, _)| *ts >= start_time && *ts < end_time)
-            .map(|(_, text)| text.as_str())
-            .collect::<Vec<_>>()
-            .join(" ");
-        
-        if !text.is_empty() {
-            let minutes = current_timestamp / 60;
-            let seconds = current_timestamp % 60;
-            normalized.push_str(&format!("[{}:{:02}] {}\n", minutes, seconds, text));
-        }
-        
-        current_timestamp += interval;
+// The videos to work on, each with the start its link's t= parameter asks for,
+// and the language to fetch them in
+// A playlist replaces the videos on the command line, and so does a channel,
+// with its latest uploads. Otherwise they come from the command line,
+// --input-file and --stdin, falling back to the YT_VIDEO_ID environment
+// variable, then config.json, when none were given. The language is taken from
+// --lang, YT_LANG or config.json, in that order; config.json only counts when
+// it's where the video came from
+async fn resolve_videos(
+    cli: &Cli,
+    session: &TranscriptFetcher,
+    config: Option<Config>,
+) -> Result<(Vec<(String, Option<f64>)>, Option<String>), Box<dyn Error>> {
+    let lang = requested_lang(cli);
+
+    if let Some(playlist) = &cli.playlist {
+        let playlist_id = parse_playlist_id(playlist)
+            .ok_or_else(|| format!("Not a valid playlist ID or URL: {}", playlist))?;
+        let fetcher = cli.fetcher(session, None);
+        let video_ids = fetch_playlist_video_ids(&playlist_id, fetcher.options()).await?;
+        return Ok((video_ids.into_iter().map(|id| (id, None)).collect(), lang));
     }
-    
-    normalized
+
+    // A channel without public uploads has nothing to download, which isn't an error
+    if let Some(channel) = &cli.channel {
+        let handle = parse_channel_handle(channel)
+            .ok_or_else(|| format!("Not a valid channel handle or URL: {}", channel))?;
+        let fetcher = cli.fetcher(session, None);
+        let video_ids = fetch_channel_video_ids(&handle, cli.latest, fetcher.options()).await?;
+        if video_ids.is_empty() {
+            info!("Channel @{} has no public videos", handle);
+        }
+        return Ok((video_ids.into_iter().map(|id| (id, None)).collect(), lang));
+    }
+
+    let mut inputs = cli.videos.clone();
+    if let Some(path) = &cli.input_file {
+        inputs.extend(read_video_list(BufReader::new(fs::File::open(path)?))?);
+    }
+    if cli.stdin {
+        inputs.extend(read_video_list(io::stdin().lock())?);
+    }
+
+    // Links carrying t= start their transcript there unless --start says otherwise
+    let mut videos = Vec::new();
+    for input in &inputs {
+        videos.push(
+            parse_video_link(input).ok_or_else(|| format!("Not a valid video ID or URL: {}", input))?,
+        );
+    }
+    if !videos.is_empty() {
+        return Ok((videos, lang));
+    }
+
+    if let Some(input) = env_var("YT_VIDEO_ID") {
+        let video = parse_video_link(&input)
+            .ok_or_else(|| format!("YT_VIDEO_ID is not a valid video ID or URL: {}", input))?;
+        return Ok((vec![video], lang));
+    }
+
+    let config = match config {
+        Some(config) => config,
+        None => read_config()?.ok_or(
+            "Failed to read config.json. Pass a video ID, set YT_VIDEO_ID or make sure it exists in the project root.",
+        )?,
+    };
+    let video_id = config
+        .resolve_video_id()
+        .ok_or("config.json must contain a valid video_id or video_url")?;
+    Ok((vec![(video_id, None)], lang.or(config.lang)))
 }
-// Timstamp line end
 
+// Do what the flags ask for with the videos, however they were picked; a video
+// without captions is reported and skipped so the rest still gets downloaded
+// Listings go to `out`. Returns false when --check finds a video without the
+// captions asked for
+async fn run(
+    cli: &Cli,
+    session: &TranscriptFetcher,
+    formats: &[OutputFormat],
+    videos: Vec<(String, Option<f64>)>,
+    lang: Option<&str>,
+    out: &mut impl Write,
+) -> Result<bool, Box<dyn Error>> {
+    if videos.is_empty() {
+        return Ok(true);
+    }
+    let video_ids: Vec<String> = videos.iter().map(|(id, _)| id.clone()).collect();
+
+    if cli.writes_to_stdout() && formats.len() > 1 {
+        return Err("Only one format can be written to stdout".into());
+    }
+    if video_ids.len() > 1 && cli.output.is_some() && !cli.writes_to_stdout() && !cli.append {
+        return Err("--output can only be used with a single video (or with --append)".into());
+    }
+    if cli.append && formats.iter().any(|f| !matches!(f, OutputFormat::Txt | OutputFormat::Md)) {
+        return Err("--append only works with the txt and md formats".into());
+    }
+
+    if cli.dry_run {
+        print_plan(cli, formats, &video_ids, out)?;
+        return Ok(true);
+    }
+
+    if cli.check {
+        return check_captions(cli, session, &video_ids, lang, out).await;
+    }
+
+    if cli.list_langs {
+        for video_id in &video_ids {
+            if video_ids.len() > 1 {
+                writeln!(out, "{}:", video_id)?;
+            }
+            for track in cli.fetcher(session, None).list_captions(video_id).await? {
+                writeln!(
+                    out,
+                    "{}\t{}{}",
+                    track.language_code,
+                    track.name.as_deref().unwrap_or(""),
+                    if track.is_auto_generated { " (auto-generated)" } else { "" }
+                )?;
+            }
+        }
+        return Ok(true);
+    }
+
+    if cli.words {
+        for video_id in &video_ids {
+            if video_ids.len() > 1 {
+                writeln!(out, "{}:", video_id)?;
+            }
+            let words = cli.fetcher(session, lang).word_timings(video_id).await?;
+            for word in words.iter().take(cli.limit.unwrap_or(usize::MAX)) {
+                writeln!(out, "{} {}", word.format_time(), word.word)?;
+            }
+        }
+        return Ok(true);
+    }
+
+    if cli.raw_xml {
+        save_raw_captions(cli, session, &video_ids, lang).await?;
+        return Ok(true);
+    }
+
+    if let [(video_id, link_start)] = videos.as_slice() {
+        let result = process_video_langs(cli, session, formats, video_id, lang, *link_start).await;
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+        }
+    } else {
+        process_batch(cli, session, formats, videos, lang).await;
+    }
+    Ok(true)
+}
+
+// Report for each video whether it has captions (in `lang`, when given) and
+// which languages it offers, one tab-separated line each, without downloading
+// any. Returns whether they all do
+async fn check_captions(
+    cli: &Cli,
+    session: &TranscriptFetcher,
+    video_ids: &[String],
+    lang: Option<&str>,
+    out: &mut impl Write,
+) -> Result<bool, Box<dyn Error>> {
+    let mut all_found = true;
+    for video_id in video_ids {
+        let tracks = match cli.fetcher(session, None).list_captions(video_id).await {
+            Ok(tracks) => tracks,
+            Err(e) => {
+                writeln!(out, "{}\terror\t{}", video_id, e)?;
+                all_found = false;
+                continue;
+            }
+        };
+        let codes: Vec<&str> = tracks.iter().map(|t| t.language_code.as_str()).collect();
+        let found = match lang {
+            Some(code) => tracks.iter().any(|t| t.matches_language(code)),
+            None => !tracks.is_empty(),
+        };
+        let status = if found { "ok" } else { "missing" };
+        writeln!(out, "{}\t{}\t{}", video_id, status, codes.join(","))?;
+        all_found &= found;
+    }
+    Ok(all_found)
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    // First, let's normalize any existing transcripts if specified
-    if let Ok(content) = std::fs::read_to_string("transcript_RcYjXbSJBN8.txt") {
-        println!("Normalizing existing transcript...");
-        let normalized = normalize_timestamps(&content);
-        println!("Normalized transcript:");
-        println!("{}", normalized);
-        // Optionally save the normalized version
-        fs::write("transcript_RcYjXbSJBN8_normalized.txt", normalized)?;
-    }
-
-    // Then proceed with the original main function logic
-    let config_text = fs::read_to_string("config.json")
-        .expect("Failed to read config.json. Make sure it exists in the project root.");
-    
-    let config: Config = serde_json::from_str(&config_text)?;
-    
-    println!("Starting transcript download for video ID: {}", config.video_id);
-    
-    match get_transcript(&config.video_id).await {
-        Ok(transcript) => {
-            println!("\nTranscript found! ({} lines)\n", transcript.len());
-            
-            // Save to file
-            save_transcript(&transcript, &config.video_id)?;
-            println!("\nTranscript saved to transcript_{}.txt", config.video_id);
-
-            // Display on console
-            for item in transcript {
-                println!("{} {}", item.format_time(), item.text);
+    let mut cli = Cli::parse();
+
+    init_logging(&cli)?;
+
+    if let Some(command) = &cli.command {
+        return run_command(command, &mut io::stdout().lock());
+    }
+
+    // config.json is only read when something is left to take from it
+    let mut config = None;
+    if cli.format.is_none() && env_var("YT_FORMAT").is_none() {
+        config = read_config()?;
+    }
+    let formats = resolve_format(&cli, config.as_ref())?;
+    // Every fetcher of the run starts from this one, so a caption track is
+    // downloaded once however many times it's asked for
+    let session = TranscriptFetcher::new();
+
+    cli.expand_prose();
+
+    create_output_dir(&cli)?;
+
+    let (videos, lang) = resolve_videos(&cli, &session, config).await?;
+    if !run(&cli, &session, &formats, videos, lang.as_deref(), &mut io::stdout()).await? {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_video_output_and_language() {
+        let cli = Cli::try_parse_from([
+            "youtube_transcript",
+            "dQw4w9WgXcQ",
+            "--output",
+            "talk.txt",
+            "--lang",
+            "es",
+        ])
+        .unwrap();
+        assert_eq!(cli.videos, ["dQw4w9WgXcQ"]);
+        assert_eq!(cli.output.as_deref(), Some(Path::new("talk.txt")));
+        assert_eq!(cli.lang.as_deref(), Some("es"));
+    }
+
+    #[test]
+    fn no_video_leaves_the_config_fallback() {
+        let cli = Cli::try_parse_from(["youtube_transcript"]).unwrap();
+        assert!(cli.videos.is_empty());
+        assert!(cli.output.is_none() && cli.lang.is_none());
+    }
+
+    #[test]
+    fn config_accepts_a_link_or_a_bare_id() {
+        let config: Config =
+            serde_json::from_str(r#"{"video_url": "https://youtu.be/dQw4w9WgXcQ?t=5"}"#).unwrap();
+        assert_eq!(config.resolve_video_id().as_deref(), Some("dQw4w9WgXcQ"));
+
+        let config: Config = serde_json::from_str(r#"{"video_id": "dQw4w9WgXcQ"}"#).unwrap();
+        assert_eq!(config.resolve_video_id().as_deref(), Some("dQw4w9WgXcQ"));
+
+        let config: Config = serde_json::from_str(r#"{"video_id": "nope"}"#).unwrap();
+        assert_eq!(config.resolve_video_id(), None);
+    }
+
+    // The formats the run would resolve, short of YT_FORMAT and config.json
+    fn formats(cli: &Cli) -> Vec<OutputFormat> {
+        cli.format.clone().unwrap_or_default().0
+    }
+
+    // A CLI parsed from these arguments, with the requests going to `server`
+    // and nothing read from or written to the real cache
+    fn cli_for(server: &mock_server::MockServer, args: &[&str]) -> Cli {
+        let mut argv = vec!["youtube_transcript", "--quiet", "--no-cache", "--base-url", server.url()];
+        argv.extend_from_slice(args);
+        Cli::try_parse_from(argv).unwrap()
+    }
+
+    #[tokio::test]
+    async fn batch_writes_every_video_and_survives_a_failure() {
+        let server = mock_server::MockServer::start();
+        let ids = ["aaaaaaaaaa1", "bbbbbbbbbb2", "cccccccccc3", "dddddddddd4"];
+        for id in ids {
+            let captions = mock_server::json3_body(&[(0, 1000, id)]);
+            mock_server::mock_video(&server, id, &["en"], &captions);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let dir_arg = dir.path().to_str().unwrap();
+        let cli = cli_for(&server, &["--output-dir", dir_arg, "--concurrency", "3"]);
+
+        // The last video isn't mocked, so it fails without stopping the others
+        let videos = ids.iter().chain(["eeeeeeeeee5"].iter()).map(|id| (id.to_string(), None)).collect();
+        process_batch(&cli, &TranscriptFetcher::new(), &formats(&cli), videos, None).await;
+
+        for id in ids {
+            let content = fs::read_to_string(dir.path().join(format!("transcript_{}.txt", id))).unwrap();
+            assert_eq!(content, format!("[00:00] {}\n", id));
+        }
+        assert!(!dir.path().join("transcript_eeeeeeeeee5.txt").exists());
+    }
+
+    fn item(text: &str, start: f64, duration: f64) -> TranscriptItem {
+        TranscriptItem {
+            text: text.to_string(),
+            start,
+            duration,
+            index: 0,
+        }
+    }
+
+    #[test]
+    fn stdout_gets_exactly_the_rendered_transcript() {
+        let cli = Cli::try_parse_from(["youtube_transcript", "dQw4w9WgXcQ", "-o", "-", "-f", "srt"]).unwrap();
+        assert!(cli.writes_to_stdout());
+
+        let transcript = [item("Hello", 0.0, 1.5), item("World", 1.5, 1.0)];
+        let mut stdout = Vec::new();
+        let metadata = VideoMetadata::default();
+        write_formats(&cli, &formats(&cli), &mut stdout, "dQw4w9WgXcQ", &metadata, &transcript).unwrap();
+        assert_eq!(
+            String::from_utf8(stdout).unwrap(),
+            "1\n00:00:00,000 --> 00:00:01,500\nHello\n\n2\n00:00:01,500 --> 00:00:02,500\nWorld\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn videos_listed_on_stdin_are_all_processed() {
+        let server = mock_server::MockServer::start();
+        for id in ["aaaaaaaaaa1", "bbbbbbbbbb2"] {
+            mock_server::mock_video(&server, id, &["en"], &mock_server::json3_body(&[(0, 1000, id)]));
+        }
+        let stdin = io::Cursor::new(
+            "# videos to fetch\naaaaaaaaaa1\n\n  https://youtu.be/bbbbbbbbbb2  \n#cccccccccc3\n",
+        );
+        let inputs = read_video_list(stdin).unwrap();
+        assert_eq!(inputs, ["aaaaaaaaaa1", "https://youtu.be/bbbbbbbbbb2"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let cli = cli_for(&server, &["--stdin", "--output-dir", dir.path().to_str().unwrap()]);
+        assert!(cli.stdin);
+        let videos = inputs.iter().map(|input| parse_video_link(input).unwrap()).collect();
+        process_batch(&cli, &TranscriptFetcher::new(), &formats(&cli), videos, None).await;
+
+        assert_eq!(
+            file_names(dir.path()),
+            ["transcript_aaaaaaaaaa1.txt", "transcript_bbbbbbbbbb2.txt"]
+        );
+        assert_eq!(server.hits("/watch"), 2);
+    }
+
+    #[tokio::test]
+    async fn skip_existing_leaves_present_files_alone() {
+        let server = mock_server::MockServer::start();
+        for id in ["aaaaaaaaaa1", "bbbbbbbbbb2"] {
+            mock_server::mock_video(&server, id, &["en"], &mock_server::json3_body(&[(0, 1000, id)]));
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let edited = dir.path().join("transcript_aaaaaaaaaa1.txt");
+        fs::write(&edited, "my edits\n").unwrap();
+        let dir_arg = dir.path().to_str().unwrap();
+
+        let cli = cli_for(&server, &["--skip-existing", "--output-dir", dir_arg]);
+        let videos = vec![("aaaaaaaaaa1".to_string(), None), ("bbbbbbbbbb2".to_string(), None)];
+        process_batch(&cli, &TranscriptFetcher::new(), &formats(&cli), videos.clone(), None).await;
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "my edits\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("transcript_bbbbbbbbbb2.txt")).unwrap(),
+            "[00:00] bbbbbbbbbb2\n"
+        );
+        // The skipped video isn't even downloaded
+        assert_eq!(server.hits("/watch?v=aaaaaaaaaa1"), 0);
+
+        let cli = cli_for(&server, &["--overwrite", "--output-dir", dir_arg]);
+        process_batch(&cli, &TranscriptFetcher::new(), &formats(&cli), videos, None).await;
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "[00:00] aaaaaaaaaa1\n");
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn format_all_writes_every_extension_from_one_fetch() {
+        let server = mock_server::MockServer::start();
+        let captions = mock_server::json3_body(&[(0, 1000, "hi")]);
+        mock_server::mock_video(&server, "dQw4w9WgXcQ", &["en"], &captions);
+        let dir = tempfile::tempdir().unwrap();
+        let cli = cli_for(&server, &["-f", "all", "--output-dir", dir.path().to_str().unwrap()]);
+
+        process_video(&cli, &TranscriptFetcher::new(), &formats(&cli), "dQw4w9WgXcQ", None, None).await.unwrap();
+        let extensions = ["csv", "json", "jsonl", "md", "srt", "txt", "vtt"];
+        let expected: Vec<String> =
+            extensions.iter().map(|ext| format!("transcript_dQw4w9WgXcQ.{}", ext)).collect();
+        assert_eq!(file_names(dir.path()), expected);
+        assert_eq!(server.hits("/watch"), 1);
+        assert_eq!(server.hits("/api/timedtext"), 1);
+    }
+
+    #[test]
+    fn formats_parse_as_a_list_without_duplicates() {
+        let FormatList(formats) = parse_formats("srt, VTT,srt").unwrap();
+        assert_eq!(formats, [OutputFormat::Srt, OutputFormat::Vtt]);
+        assert!(parse_formats("srt,docx").is_err());
+    }
+
+    #[tokio::test]
+    async fn output_dir_is_created_and_keeps_the_generated_name() {
+        let server = mock_server::MockServer::start();
+        let captions = mock_server::json3_body(&[(0, 1000, "hi")]);
+        mock_server::mock_video(&server, "dQw4w9WgXcQ", &["en"], &captions);
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = dir.path().join("transcripts").join("2024");
+        let cli = cli_for(&server, &["--output-dir", output_dir.to_str().unwrap()]);
+
+        create_output_dir(&cli).unwrap();
+        process_video(&cli, &TranscriptFetcher::new(), &formats(&cli), "dQw4w9WgXcQ", None, None).await.unwrap();
+        assert_eq!(file_names(&output_dir), ["transcript_dQw4w9WgXcQ.txt"]);
+        assert_eq!(file_names(dir.path()), ["transcripts"]);
+    }
+
+    #[test]
+    fn dry_run_lists_the_planned_paths_and_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("transcript_aaaaaaaaaa1.txt"), "old\n").unwrap();
+        let dry_run = |output_dir: &Path| {
+            let output_dir = output_dir.to_str().unwrap();
+            let args = ["youtube_transcript", "--dry-run", "--output-dir", output_dir, "-f", "txt,srt"];
+            Cli::try_parse_from(args).unwrap()
+        };
+        let cli = dry_run(dir.path());
+        let missing = dir.path().join("out");
+        create_output_dir(&dry_run(&missing)).unwrap();
+
+        let mut listed = Vec::new();
+        let ids = ["aaaaaaaaaa1".to_string(), "bbbbbbbbbb2".to_string()];
+        print_plan(&cli, &formats(&cli), &ids, &mut listed).unwrap();
+        let path = |name: &str| dir.path().join(name).display().to_string();
+        assert_eq!(
+            String::from_utf8(listed).unwrap(),
+            format!(
+                "aaaaaaaaaa1 -> {} (exists, would be overwritten)\n\
+                 aaaaaaaaaa1 -> {}\n\
+                 bbbbbbbbbb2 -> {}\n\
+                 bbbbbbbbbb2 -> {}\n",
+                path("transcript_aaaaaaaaaa1.txt"),
+                path("transcript_aaaaaaaaaa1.srt"),
+                path("transcript_bbbbbbbbbb2.txt"),
+                path("transcript_bbbbbbbbbb2.srt"),
+            )
+        );
+        assert_eq!(file_names(dir.path()), ["transcript_aaaaaaaaaa1.txt"]);
+        assert!(!missing.exists());
+    }
+
+    // Whatever the logger under test writes
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn the_progress_bar_runs_over_a_batch_and_logs_go_around_it() {
+        let server = mock_server::MockServer::start();
+        let ids = ["aaaaaaaaaa1", "bbbbbbbbbb2", "cccccccccc3"];
+        for id in ids {
+            let captions = mock_server::json3_body(&[(0, 1000, id)]);
+            mock_server::mock_video(&server, id, &["en"], &captions);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        // Without --quiet, so the bar is really made (indicatif keeps it off screen,
+        // as stderr isn't a terminal under test)
+        let args = ["youtube_transcript", "--no-cache", "--base-url", server.url()];
+        let mut cli = Cli::try_parse_from(args).unwrap();
+        cli.output_dir = Some(dir.path().to_path_buf());
+        let videos = ids.iter().map(|id| (id.to_string(), None)).collect();
+        process_batch(&cli, &TranscriptFetcher::new(), &formats(&cli), videos, None).await;
+        assert_eq!(file_names(dir.path()).len(), 3);
+
+        let captured = Captured::default();
+        let logger = ProgressLogger(
+            env_logger::Builder::new()
+                .filter_level(LevelFilter::Info)
+                .format_timestamp(None)
+                .format_target(false)
+                .target(env_logger::Target::Pipe(Box::new(captured.clone())))
+                .build(),
+        );
+        let bar = ProgressBar::hidden();
+        *ACTIVE_PROGRESS.lock().unwrap() = Some(bar.clone());
+        for level in [log::Level::Info, log::Level::Debug] {
+            let record = log::Record::builder().args(format_args!("halfway")).level(level).build();
+            log::Log::log(&logger, &record);
+        }
+        bar.finish_and_clear();
+        *ACTIVE_PROGRESS.lock().unwrap() = None;
+        let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(logged, "[INFO ] halfway\n");
+    }
+
+    #[tokio::test]
+    async fn append_adds_each_video_under_its_heading_in_order() {
+        let server = mock_server::MockServer::start();
+        for id in ["aaaaaaaaaa1", "bbbbbbbbbb2"] {
+            let captions = mock_server::json3_body(&[(0, 1000, "first line"), (7000, 1000, id)]);
+            mock_server::mock_video(&server, id, &["en"], &captions);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let combined = dir.path().join("combined.txt");
+        let cli = cli_for(&server, &["--append", "--output", combined.to_str().unwrap()]);
+
+        // Two separate runs, one video each
+        process_video(&cli, &TranscriptFetcher::new(), &formats(&cli), "aaaaaaaaaa1", None, None).await.unwrap();
+        process_video(&cli, &TranscriptFetcher::new(), &formats(&cli), "bbbbbbbbbb2", None, None).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(&combined).unwrap(),
+            "# Mock video (aaaaaaaaaa1)\n[00:00] first line\n[00:06] aaaaaaaaaa1\n\n\
+             # Mock video (bbbbbbbbbb2)\n[00:00] first line\n[00:06] bbbbbbbbbb2\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_concurrent_batch_never_interleaves_appended_videos() {
+        let server = mock_server::MockServer::start();
+        let ids: Vec<String> = (0..8).map(|i| format!("video{:06}", i)).collect();
+        let texts: Vec<String> = (0..200).map(|i| format!("line {}", i)).collect();
+        let lines: Vec<(u64, u64, &str)> =
+            texts.iter().enumerate().map(|(i, text)| (i as u64 * 1000, 900, text.as_str())).collect();
+        for id in &ids {
+            mock_server::mock_video(&server, id, &["en"], &mock_server::json3_body(&lines));
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let combined = dir.path().join("combined.txt");
+        let output = combined.to_str().unwrap();
+        let cli = cli_for(&server, &["--append", "--output", output, "--concurrency", "8"]);
+
+        let videos = ids.iter().map(|id| (id.clone(), None)).collect();
+        process_batch(&cli, &TranscriptFetcher::new(), &formats(&cli), videos, None).await;
+        let content = fs::read_to_string(&combined).unwrap();
+        let blocks: Vec<&str> = content.split("# Mock video (").skip(1).collect();
+        assert_eq!(blocks.len(), ids.len());
+        // Every video has the same captions, so an interleaved write would show
+        // up as a body that differs from the others
+        let (_, first_body) = blocks[0].split_once(")\n").unwrap();
+        assert!(first_body.starts_with("[00:00] line 0 ") && first_body.ends_with(" line 199\n\n"));
+        for block in blocks {
+            let (id, body) = block.split_once(")\n").unwrap();
+            assert!(ids.iter().any(|known| known == id));
+            assert_eq!(body, first_body);
+        }
+    }
+
+    #[test]
+    fn yt_lang_applies_unless_lang_is_given() {
+        // Only this test touches YT_LANG
+        std::env::set_var("YT_LANG", "fr");
+        let env = Cli::try_parse_from(["youtube_transcript", "--playlist", "PLabcdefghijkl"]).unwrap();
+        let flag = Cli::try_parse_from(["youtube_transcript", "--channel", "@chan", "--lang", "de"])
+            .unwrap();
+        let from_env = requested_lang(&env);
+        let from_flag = requested_lang(&flag);
+        std::env::remove_var("YT_LANG");
+
+        assert_eq!(from_env.as_deref(), Some("fr"));
+        assert_eq!(from_flag.as_deref(), Some("de"));
+        assert_eq!(requested_lang(&env), None);
+    }
+
+    #[tokio::test]
+    async fn an_interrupt_stops_new_videos_but_finishes_the_current_one() {
+        let server = mock_server::MockServer::start();
+        let ids = ["aaaaaaaaaa1", "bbbbbbbbbb2", "cccccccccc3"];
+        for id in &ids[1..] {
+            let captions = mock_server::json3_body(&[(0, 1000, id)]);
+            mock_server::mock_video(&server, id, &["en"], &captions);
+        }
+        // The first video's captions are slow, so the interrupt lands while it's in flight
+        let player = mock_server::player_response(server.url(), ids[0], &["en"]);
+        server.mock("/watch?v=aaaaaaaaaa1", mock_server::MockResponse::ok(mock_server::watch_page(&player)));
+        let slow = mock_server::json3_body(&[(0, 1000, ids[0])]);
+        server.mock(
+            "/api/timedtext?v=aaaaaaaaaa1&lang=en&fmt=json3",
+            mock_server::MockResponse::ok(slow).delay(Duration::from_millis(400)),
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let dir_arg = dir.path().to_str().unwrap();
+        let cli = cli_for(&server, &["--output-dir", dir_arg, "--concurrency", "1"]);
+
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let interrupt = tokio::spawn({
+            let interrupted = Arc::clone(&interrupted);
+            async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                interrupted.store(true, Ordering::SeqCst);
             }
+        });
+        let videos = ids.iter().map(|id| (id.to_string(), None)).collect();
+        let progress = ProgressBar::hidden();
+        let session = TranscriptFetcher::new();
+        let counts = download_videos(&cli, &session, &formats(&cli), videos, None, &interrupted, &progress).await;
+        interrupt.await.unwrap();
+
+        assert_eq!(counts, (1, 0));
+        assert_eq!(file_names(dir.path()), ["transcript_aaaaaaaaaa1.txt"]);
+        assert_eq!(server.hits("/watch"), 1);
+    }
+
+    #[tokio::test]
+    async fn limit_keeps_only_the_first_captions() {
+        let server = mock_server::MockServer::start();
+        let lines: Vec<(u64, u64, String)> =
+            (0..40).map(|i| (i * 2000, 2000, format!("line {}", i))).collect();
+        let lines: Vec<(u64, u64, &str)> = lines.iter().map(|(s, d, t)| (*s, *d, t.as_str())).collect();
+        mock_server::mock_video(&server, "dQw4w9WgXcQ", &["en"], &mock_server::json3_body(&lines));
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("preview.jsonl");
+        let output_arg = output.to_str().unwrap();
+        let cli = cli_for(&server, &["--limit", "5", "--format", "jsonl", "--output", output_arg]);
+
+        process_video(&cli, &TranscriptFetcher::new(), &formats(&cli), "dQw4w9WgXcQ", None, None).await.unwrap();
+
+        let content = fs::read_to_string(&output).unwrap();
+        let texts: Vec<String> = content
+            .lines()
+            .map(|line| serde_json::from_str::<TranscriptItem>(line).unwrap().text)
+            .collect();
+        assert_eq!(texts, ["line 0", "line 1", "line 2", "line 3", "line 4"]);
+    }
+
+    #[test]
+    fn normalize_regroups_a_transcript_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("talk.txt");
+        fs::write(&input, "[00:01] Hello\n[00:04] there\n[00:07] everyone\n[00:13] welcome\n").unwrap();
+        let output = dir.path().join("normalized.txt");
+        let run = |args: &[&str]| {
+            let mut argv = vec!["youtube_transcript", "normalize", input.to_str().unwrap()];
+            argv.extend_from_slice(args);
+            let cli = Cli::try_parse_from(argv).unwrap();
+            let mut out = Vec::new();
+            run_command(cli.command.as_ref().unwrap(), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(run(&["--interval", "6"]), "[00:00] Hello there\n[00:06] everyone\n[00:12] welcome\n");
+        assert_eq!(run(&["--interval", "10", "--output", output.to_str().unwrap()]), "");
+        assert_eq!(fs::read_to_string(&output).unwrap(), "[00:00] Hello there everyone\n[00:10] welcome\n");
+    }
+
+    #[tokio::test]
+    async fn all_langs_writes_one_file_per_language_and_skips_failures() {
+        let server = mock_server::MockServer::start();
+        let player = mock_server::player_response(server.url(), "dQw4w9WgXcQ", &["en", "es", "fr"]);
+        server.mock("/watch?v=dQw4w9WgXcQ", mock_server::MockResponse::ok(mock_server::watch_page(&player)));
+        for (lang, text) in [("en", "hello"), ("es", "hola")] {
+            server.mock(
+                &format!("/api/timedtext?v=dQw4w9WgXcQ&lang={}&fmt=json3", lang),
+                mock_server::MockResponse::ok(mock_server::json3_body(&[(0, 1000, text)])),
+            );
         }
-        Err(e) => eprintln!("Error: {}", e),
+        server.mock("/api/timedtext", mock_server::MockResponse::status(404, "no such track"));
+        let dir = tempfile::tempdir().unwrap();
+        let cli = cli_for(&server, &["--all-langs", "--output-dir", dir.path().to_str().unwrap()]);
+
+        let session = TranscriptFetcher::new();
+        process_video_langs(&cli, &session, &formats(&cli), "dQw4w9WgXcQ", None, None).await.unwrap();
+
+        assert_eq!(file_names(dir.path()), ["transcript_dQw4w9WgXcQ.en.txt", "transcript_dQw4w9WgXcQ.es.txt"]);
+        let spanish = fs::read_to_string(dir.path().join("transcript_dQw4w9WgXcQ.es.txt")).unwrap();
+        assert_eq!(spanish, "[00:00] hola\n");
+        // The caption tracks of every language come from the one watch page
+        assert_eq!(server.hits("/watch"), 1);
     }
 
-    Ok(())
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn raw_xml_is_saved_byte_for_byte() {
+        let server = mock_server::MockServer::start();
+        let player = mock_server::player_response(server.url(), "dQw4w9WgXcQ", &["en"]);
+        server.mock("/watch?v=dQw4w9WgXcQ", mock_server::MockResponse::ok(mock_server::watch_page(&player)));
+        // Not valid UTF-8; lossy decoding would turn the 0xff into U+FFFD
+        let mut body = b"<?xml version=\"1.0\" ?><transcript>\r\n<text start=\"0\" dur=\"1\">caf".to_vec();
+        body.extend_from_slice(&[0xe9, 0xff]);
+        body.extend_from_slice(b"</text>\r\n</transcript>");
+        server.mock("/api/timedtext?v=dQw4w9WgXcQ&lang=en", mock_server::MockResponse::ok(body.clone()));
+        let dir = tempfile::tempdir().unwrap();
+        let cli = cli_for(&server, &["--raw-xml", "--output-dir", dir.path().to_str().unwrap()]);
+
+        save_raw_captions(&cli, &TranscriptFetcher::new(), &["dQw4w9WgXcQ".to_string()], None).await.unwrap();
+
+        assert_eq!(fs::read(dir.path().join("transcript_dQw4w9WgXcQ.xml")).unwrap(), body);
+    }
+
+    #[test]
+    fn the_format_comes_from_the_flag_then_yt_format_then_the_config() {
+        let config = Config {
+            video_url: None,
+            video_id: None,
+            lang: None,
+            format: Some("srt".to_string()),
+        };
+        let formats = |args: &[&str], config: Option<&Config>| {
+            let mut argv = vec!["youtube_transcript", "dQw4w9WgXcQ"];
+            argv.extend_from_slice(args);
+            resolve_format(&Cli::try_parse_from(argv).unwrap(), config)
+        };
+
+        // Only this test touches YT_FORMAT
+        std::env::set_var("YT_FORMAT", "not-a-format");
+        let flag_wins = formats(&["--format", "vtt"], Some(&config));
+        let bad_env = formats(&[], Some(&config));
+        std::env::set_var("YT_FORMAT", "json,csv");
+        let env_wins = formats(&[], Some(&config));
+        std::env::remove_var("YT_FORMAT");
+
+        // An invalid YT_FORMAT doesn't matter when --format is given
+        assert_eq!(flag_wins.unwrap(), [OutputFormat::Vtt]);
+        assert!(bad_env.unwrap_err().starts_with("YT_FORMAT: "));
+        assert_eq!(env_wins.unwrap(), [OutputFormat::Json, OutputFormat::Csv]);
+        assert_eq!(formats(&[], Some(&config)).unwrap(), [OutputFormat::Srt]);
+        assert_eq!(formats(&[], None).unwrap(), [OutputFormat::Txt]);
+    }
+
+    #[tokio::test]
+    async fn prose_is_one_paragraph_of_whole_sentences() {
+        let server = mock_server::MockServer::start();
+        let captions = mock_server::json3_body(&[
+            (0, 1000, "so what  we"),
+            (1000, 1000, "found was"),
+            (2000, 1000, "surprising."),
+            (3000, 1000, "[Music]"),
+            (4000, 1000, "Did it work?"),
+            (5000, 1000, "  it did\n"),
+        ]);
+        mock_server::mock_video(&server, "dQw4w9WgXcQ", &["en"], &captions);
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("prose.txt");
+        let mut cli = cli_for(&server, &["--prose", "--strip-annotations", "--output", output.to_str().unwrap()]);
+        cli.expand_prose();
+
+        let session = TranscriptFetcher::new();
+        process_video_langs(&cli, &session, &formats(&cli), "dQw4w9WgXcQ", None, None).await.unwrap();
+
+        let prose = fs::read_to_string(&output).unwrap();
+        assert_eq!(prose.trim_end(), "so what we found was surprising. Did it work? it did");
+        assert!(!prose.contains(['[', ']']) && !prose.contains("  "), "{:?}", prose);
+        assert_eq!(prose.trim_end().lines().count(), 1);
+    }
+
+    // A playlist of aaaaaaaaaa1, bbbbbbbbbb2 and ccccccccc-3, the last two also
+    // offering French captions
+    fn mock_playlist(server: &mock_server::MockServer) {
+        server.mock(
+            "/playlist?list=PLabcdefghij",
+            mock_server::MockResponse::ok(include_str!("../tests/fixtures/playlist_page.html")),
+        );
+        for (id, langs) in [("aaaaaaaaaa1", &["en"][..]), ("bbbbbbbbbb2", &["en", "fr"]), ("ccccccccc-3", &["fr"])] {
+            mock_server::mock_video(server, id, langs, &mock_server::json3_body(&[(0, 1000, id)]));
+        }
+    }
+
+    async fn run_for(server: &mock_server::MockServer, args: &[&str]) -> (Result<bool, String>, String) {
+        let cli = cli_for(server, args);
+        let session = TranscriptFetcher::new();
+        let mut out = Vec::new();
+        let result = match resolve_videos(&cli, &session, None).await {
+            Ok((videos, lang)) => {
+                run(&cli, &session, &formats(&cli), videos, lang.as_deref(), &mut out).await
+            }
+            Err(e) => Err(e),
+        };
+        (result.map_err(|e| e.to_string()), String::from_utf8(out).unwrap())
+    }
+
+    #[tokio::test]
+    async fn a_playlist_can_be_checked_without_downloading() {
+        let server = mock_server::MockServer::start();
+        mock_playlist(&server);
+
+        let (result, out) = run_for(&server, &["--playlist", "PLabcdefghij", "--check", "--lang", "fr"]).await;
+        assert_eq!(result, Ok(false));
+        assert_eq!(
+            out,
+            "aaaaaaaaaa1\tmissing\ten\nbbbbbbbbbb2\tok\ten,fr\nccccccccc-3\tok\tfr\n"
+        );
+        assert_eq!(server.hits("/api/timedtext"), 0);
+
+        let (result, _) = run_for(&server, &["--playlist", "PLabcdefghij", "--check"]).await;
+        assert_eq!(result, Ok(true));
+    }
+
+    #[tokio::test]
+    async fn a_playlist_lists_languages_and_words_per_video() {
+        let server = mock_server::MockServer::start();
+        mock_playlist(&server);
+
+        let (result, out) = run_for(&server, &["--playlist", "PLabcdefghij", "--list-langs"]).await;
+        assert_eq!(result, Ok(true));
+        assert_eq!(
+            out,
+            "aaaaaaaaaa1:\nen\tLanguage en\nbbbbbbbbbb2:\nen\tLanguage en\nfr\tLanguage fr\n\
+             ccccccccc-3:\nfr\tLanguage fr\n"
+        );
+
+        let (result, out) = run_for(&server, &["--playlist", "PLabcdefghij", "--words", "--lang", "fr"]).await;
+        assert!(result.is_err(), "aaaaaaaaaa1 has no French captions");
+        assert_eq!(out, "aaaaaaaaaa1:\n");
+    }
+
+    #[tokio::test]
+    async fn a_channel_goes_through_the_same_guards_and_modes() {
+        let server = mock_server::MockServer::start();
+        server.mock(
+            "/@mockchannel/videos",
+            mock_server::MockResponse::ok(include_str!("../tests/fixtures/channel_page.html")),
+        );
+        for id in ["dddddddddd4", "eeeeeeeeee5"] {
+            mock_server::mock_video(&server, id, &["en"], &mock_server::json3_body(&[(0, 500, id)]));
+            server.mock(
+                &format!("/api/timedtext?v={}&lang=en", id),
+                mock_server::MockResponse::ok("<transcript></transcript>"),
+            );
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let dir_arg = dir.path().to_str().unwrap();
+
+        let channel = ["--channel", "@mockchannel"];
+        let (result, _) =
+            run_for(&server, &[&channel[..], &["--stdout", "--format", "srt,vtt"]].concat()).await;
+        assert_eq!(result.unwrap_err(), "Only one format can be written to stdout");
+        assert_eq!(server.hits("/watch"), 0);
+        // --append needs --output, which a channel already rules out
+        let append = [&channel[..], &["--append", "--output", "all.md"]].concat();
+        assert!(Cli::try_parse_from([&["yt"][..], &append].concat()).is_err());
+
+        let (result, out) =
+            run_for(&server, &[&channel[..], &["--latest", "2", "--check"]].concat()).await;
+        assert_eq!(result, Ok(true));
+        assert_eq!(out, "dddddddddd4\tok\ten\neeeeeeeeee5\tok\ten\n");
+
+        let raw = [&channel[..], &["--latest", "2", "--raw-xml", "--output-dir", dir_arg]].concat();
+        let (result, _) = run_for(&server, &raw).await;
+        assert_eq!(result, Ok(true));
+        assert_eq!(
+            file_names(dir.path()),
+            ["transcript_dddddddddd4.xml", "transcript_eeeeeeeeee5.xml"]
+        );
+    }
+}
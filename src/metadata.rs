@@ -0,0 +1,76 @@
+use crate::{parse_description_chapters, Chapter};
+use serde::{Deserialize, Serialize};
+
+// Details about the video itself, read from the videoDetails object of the
+// same player response the captions come from. YouTube leaves fields out for
+// some videos, so every one of them is optional
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VideoMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub length_seconds: Option<u64>,
+    pub view_count: Option<u64>,
+    // Taken from the description here; the watch page's chapter markers replace
+    // them when it has any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chapters: Vec<Chapter>,
+}
+
+// Numbers in videoDetails arrive as strings ("lengthSeconds": "212"), but
+// accept plain JSON numbers too in case that ever changes
+fn number_field(details: &serde_json::Value, key: &str) -> Option<u64> {
+    let value = details.get(key)?;
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+pub fn parse_video_metadata(player_response: &serde_json::Value) -> VideoMetadata {
+    let Some(details) = player_response.get("videoDetails") else {
+        return VideoMetadata::default();
+    };
+
+    let string_field = |key: &str| details.get(key).and_then(|v| v.as_str()).map(str::to_string);
+
+    VideoMetadata {
+        title: string_field("title"),
+        author: string_field("author"),
+        length_seconds: number_field(details, "lengthSeconds"),
+        view_count: number_field(details, "viewCount"),
+        chapters: details
+            .get("shortDescription")
+            .and_then(|d| d.as_str())
+            .map(parse_description_chapters)
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_video_details() {
+        let json = include_str!("../tests/fixtures/player_multi_track.json");
+        let metadata = parse_video_metadata(&serde_json::from_str(json).unwrap());
+        assert_eq!(
+            metadata.title.as_deref(),
+            Some("Rick Astley - Never Gonna Give You Up (Official Music Video)")
+        );
+        assert_eq!(metadata.author.as_deref(), Some("Rick Astley"));
+        assert_eq!(metadata.length_seconds, Some(212));
+        assert_eq!(metadata.view_count, Some(1_500_000_000));
+    }
+
+    #[test]
+    fn missing_fields_are_none() {
+        let player = serde_json::json!({ "videoDetails": { "title": "Only a title", "viewCount": 7 } });
+        let metadata = parse_video_metadata(&player);
+        assert_eq!(metadata.title.as_deref(), Some("Only a title"));
+        assert_eq!(metadata.author, None);
+        assert_eq!(metadata.length_seconds, None);
+        assert_eq!(metadata.view_count, Some(7));
+
+        assert_eq!(parse_video_metadata(&serde_json::json!({})), VideoMetadata::default());
+    }
+}
@@ -0,0 +1,286 @@
+// A small HTTP server on a local port that stands in for YouTube in tests.
+// Both the library's and the binary's tests use it, each only part of it
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// One canned response
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    delay: Duration,
+    chunked: bool,
+}
+
+impl MockResponse {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::status(200, body)
+    }
+
+    pub fn status(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        MockResponse {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            delay: Duration::ZERO,
+            chunked: false,
+        }
+    }
+
+    pub fn redirect(location: &str) -> Self {
+        Self::status(302, "").header("Location", location)
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    // Wait this long before answering
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    // Send the body with chunked transfer encoding, so it has no Content-Length
+    pub fn chunked(mut self) -> Self {
+        self.chunked = true;
+        self
+    }
+}
+
+// A request the server received
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    // Path and query, e.g. "/watch?v=dQw4w9WgXcQ"
+    pub target: String,
+    // Header names are lowercase
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers.iter().find(|(key, _)| *key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // Responses by target (path and query) or by path alone; the last response
+    // of a queue keeps being served once the ones before it are used up
+    routes: HashMap<String, VecDeque<MockResponse>>,
+    requests: Vec<MockRequest>,
+}
+
+impl State {
+    fn respond(&mut self, request: &MockRequest) -> MockResponse {
+        let key = [request.target.as_str(), request.path()]
+            .into_iter()
+            .find(|key| self.routes.contains_key(*key));
+        let Some(queue) = key.and_then(|key| self.routes.get_mut(key)) else {
+            return MockResponse::status(404, format!("no mock for {}", request.target));
+        };
+        if queue.len() > 1 {
+            queue.pop_front().unwrap()
+        } else {
+            queue.front().cloned().unwrap()
+        }
+    }
+}
+
+pub struct MockServer {
+    url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = Arc::clone(&shared);
+                thread::spawn(move || serve(stream, &state));
+            }
+        });
+        MockServer { url, state }
+    }
+
+    // "http://127.0.0.1:PORT", to use as FetchOptions::base_url
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Answer requests for `route` (a path, or a path with its query) with
+    // `response`. Mocking the same route again queues another response
+    pub fn mock(&self, route: &str, response: MockResponse) {
+        let mut state = self.state.lock().unwrap();
+        state.routes.entry(route.to_string()).or_default().push_back(response);
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    // How many requests went to `route`, matched the same way as mock
+    pub fn hits(&self, route: &str) -> usize {
+        self.requests()
+            .iter()
+            .filter(|request| request.target == route || request.path() == route)
+            .count()
+    }
+}
+
+fn serve(stream: TcpStream, state: &Mutex<State>) {
+    let mut reader = BufReader::new(stream);
+    while let Some(request) = read_request(&mut reader) {
+        let response = {
+            let mut state = state.lock().unwrap();
+            state.requests.push(request.clone());
+            state.respond(&request)
+        };
+        thread::sleep(response.delay);
+        if write_response(reader.get_mut(), &response).is_err() {
+            return;
+        }
+    }
+}
+
+fn read_request(reader: &mut BufReader<TcpStream>) -> Option<MockRequest> {
+    let mut line = String::new();
+    reader.read_line(&mut line).ok().filter(|read| *read > 0)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(MockRequest {
+        method,
+        target,
+        headers,
+        body,
+    })
+}
+
+fn write_response(stream: &mut TcpStream, response: &MockResponse) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {} Mock\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if response.chunked {
+        head.push_str("Transfer-Encoding: chunked\r\n\r\n");
+        stream.write_all(head.as_bytes())?;
+        for chunk in response.body.chunks(16 * 1024) {
+            stream.write_all(format!("{:x}\r\n", chunk.len()).as_bytes())?;
+            stream.write_all(chunk)?;
+            stream.write_all(b"\r\n")?;
+        }
+        stream.write_all(b"0\r\n\r\n")?;
+    } else {
+        head.push_str(&format!("Content-Length: {}\r\n\r\n", response.body.len()));
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&response.body)?;
+    }
+    stream.flush()
+}
+
+// A player response offering one caption track per language code, each
+// downloaded from /api/timedtext on the mock server
+pub fn player_response(server_url: &str, video_id: &str, langs: &[&str]) -> serde_json::Value {
+    let tracks: Vec<serde_json::Value> = langs
+        .iter()
+        .map(|lang| {
+            serde_json::json!({
+                "baseUrl": format!("{}/api/timedtext?v={}&lang={}", server_url, video_id, lang),
+                "languageCode": lang,
+                "name": { "simpleText": format!("Language {}", lang) },
+                "isTranslatable": true,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "playabilityStatus": { "status": "OK" },
+        "videoDetails": {
+            "videoId": video_id,
+            "title": "Mock video",
+            "author": "Mock author",
+            "lengthSeconds": "120",
+            "viewCount": "42",
+        },
+        "captions": {
+            "playerCaptionsTracklistRenderer": { "captionTracks": tracks }
+        },
+    })
+}
+
+// A watch page embedding `player` the way YouTube does
+pub fn watch_page(player: &serde_json::Value) -> String {
+    format!(
+        "<html><body><script>var ytInitialPlayerResponse = {};</script></body></html>",
+        player
+    )
+}
+
+// A json3 caption document with one event per (start ms, duration ms, text)
+pub fn json3_body(lines: &[(u64, u64, &str)]) -> String {
+    let events: Vec<serde_json::Value> = lines
+        .iter()
+        .map(|(start, duration, text)| {
+            serde_json::json!({
+                "tStartMs": start,
+                "dDurationMs": duration,
+                "segs": [{ "utf8": text }],
+            })
+        })
+        .collect();
+    serde_json::json!({ "wireMagic": "pb3", "events": events }).to_string()
+}
+
+// Serve a watch page for `video_id` with one caption track per language, each
+// answering with `captions`
+pub fn mock_video(server: &MockServer, video_id: &str, langs: &[&str], captions: &str) {
+    let player = player_response(server.url(), video_id, langs);
+    server.mock(&format!("/watch?v={}", video_id), MockResponse::ok(watch_page(&player)));
+    for lang in langs {
+        server.mock(
+            &format!("/api/timedtext?v={}&lang={}&fmt=json3", video_id, lang),
+            MockResponse::ok(captions),
+        );
+    }
+}
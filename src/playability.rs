@@ -0,0 +1,120 @@
+use crate::TranscriptError;
+use serde_json::Value;
+
+// Turn the player response's playabilityStatus into an error when the video
+// can't be watched, so a private or removed video isn't reported as having no
+// captions. A missing status is treated as playable
+pub(crate) fn check_playability(player_response: &Value) -> Result<(), TranscriptError> {
+    let Some(status) = player_response.get("playabilityStatus") else {
+        return Ok(());
+    };
+    let reason = status
+        .get("reason")
+        .and_then(|r| r.as_str())
+        .unwrap_or("no reason given")
+        .to_string();
+
+    match status.get("status").and_then(|s| s.as_str()) {
+        Some("LOGIN_REQUIRED") if reason.to_lowercase().contains("private") => Err(TranscriptError::Private),
+        // Age-restricted videos ask for a login too, which cookies can provide
+        Some("LOGIN_REQUIRED") => Err(TranscriptError::LoginRequired { reason }),
+        // A scheduled premiere or a live stream that hasn't started yet
+        Some("LIVE_STREAM_OFFLINE") => Err(TranscriptError::LiveStreamUnsupported),
+        Some("ERROR") | Some("UNPLAYABLE") | Some("CONTENT_CHECK_REQUIRED") => {
+            Err(TranscriptError::VideoUnavailable { reason })
+        }
+        _ => Ok(()),
+    }
+}
+
+// Whether the video is live right now or still upcoming, so it can't have
+// finished captions yet. isLiveContent alone isn't enough: it stays set on the
+// recording once a stream has ended, and those do get regular captions
+pub(crate) fn is_live_now(player_response: &Value) -> bool {
+    let details = player_response.get("videoDetails");
+    let flag = |name: &str| {
+        details
+            .and_then(|d| d.get(name))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    };
+    flag("isLive") || flag("isUpcoming")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caption_base_url;
+
+    fn fixture(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn private_videos_are_reported_as_private() {
+        let parsed = fixture(include_str!("../tests/fixtures/player_private.json"));
+        assert!(matches!(check_playability(&parsed), Err(TranscriptError::Private)));
+    }
+
+    #[test]
+    fn age_restricted_videos_ask_for_a_login() {
+        let parsed = fixture(include_str!("../tests/fixtures/player_age_restricted.json"));
+        assert!(matches!(
+            check_playability(&parsed),
+            Err(TranscriptError::LoginRequired { reason }) if reason == "Sign in to confirm your age"
+        ));
+    }
+
+    #[test]
+    fn removed_videos_are_unavailable_with_the_reason() {
+        let parsed = fixture(include_str!("../tests/fixtures/player_unavailable.json"));
+        assert!(matches!(
+            check_playability(&parsed),
+            Err(TranscriptError::VideoUnavailable { reason })
+                if reason == "This video has been removed by the uploader"
+        ));
+    }
+
+    #[test]
+    fn playable_videos_without_captions_have_them_disabled() {
+        let parsed = fixture(include_str!("../tests/fixtures/player_captions_disabled.json"));
+        assert!(check_playability(&parsed).is_ok());
+        assert!(matches!(
+            caption_base_url(&parsed, None, None),
+            Err(TranscriptError::CaptionsDisabled)
+        ));
+    }
+
+    #[test]
+    fn a_missing_status_counts_as_playable() {
+        assert!(check_playability(&serde_json::json!({})).is_ok());
+    }
+
+    #[test]
+    fn a_live_stream_without_captions_is_unsupported() {
+        let parsed = fixture(include_str!("../tests/fixtures/player_live.json"));
+        assert!(check_playability(&parsed).is_ok());
+        assert!(is_live_now(&parsed));
+        assert!(matches!(
+            caption_base_url(&parsed, None, None),
+            Err(TranscriptError::LiveStreamUnsupported)
+        ));
+    }
+
+    #[test]
+    fn an_ended_stream_with_captions_is_fetched_as_usual() {
+        let mut parsed = fixture(include_str!("../tests/fixtures/player_multi_track.json"));
+        parsed["videoDetails"]["isLiveContent"] = Value::Bool(true);
+        assert!(!is_live_now(&parsed));
+        assert!(caption_base_url(&parsed, None, None).is_ok());
+    }
+
+    #[test]
+    fn an_offline_premiere_is_unsupported() {
+        let parsed = serde_json::json!({
+            "playabilityStatus": { "status": "LIVE_STREAM_OFFLINE", "reason": "Premieres in 2 hours" },
+            "videoDetails": { "isUpcoming": true },
+        });
+        assert!(matches!(check_playability(&parsed), Err(TranscriptError::LiveStreamUnsupported)));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::http::{read_text, HttpClient};
+use crate::{extract_assigned_json, FetchOptions, TranscriptError};
+use log::info;
+
+pub fn build_playlist_url(base_url: &str, playlist_id: &str) -> String {
+    format!("{}/playlist?list={}", base_url.trim_end_matches('/'), playlist_id)
+}
+
+fn is_valid_playlist_id(id: &str) -> bool {
+    id.len() >= 10 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Pull the playlist ID out of a link carrying a `list=` parameter
+// (https://www.youtube.com/playlist?list=PL...), or accept a bare ID
+pub fn parse_playlist_id(input: &str) -> Option<String> {
+    let input = input.trim();
+
+    for marker in ["?list=", "&list="] {
+        if let Some(idx) = input.find(marker) {
+            let id: String = input[idx + marker.len()..]
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+                .collect();
+            return is_valid_playlist_id(&id).then_some(id);
+        }
+    }
+
+    is_valid_playlist_id(input).then(|| input.to_string())
+}
+
+// Playlist (and channel) pages describe their contents in ytInitialData
+// rather than the player response
+pub fn extract_initial_data(html: &str) -> Option<&str> {
+    extract_assigned_json(html, "var ytInitialData = ")
+        .or_else(|| extract_assigned_json(html, "ytInitialData = "))
+}
+
+// Append the videoId of every renderer named in `renderers` found anywhere in
+// `value`, skipping repeats. Channel pages (crate::channel) list their uploads
+// the same way as playlists, just under other renderer names
+pub(crate) fn collect_video_ids(value: &serde_json::Value, renderers: &[&str], ids: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            if let Some(id) = renderers
+                .iter()
+                .find_map(|key| map.get(*key))
+                .and_then(|r| r.get("videoId"))
+                .and_then(|id| id.as_str())
+            {
+                if !ids.iter().any(|known| known == id) {
+                    ids.push(id.to_string());
+                }
+            }
+            for child in map.values() {
+                collect_video_ids(child, renderers, ids);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_video_ids(item, renderers, ids);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Every video listed in a playlist's ytInitialData, in playlist order
+// The renderers are nested deep inside tabs and sections whose layout YouTube
+// changes often, so we search the whole tree for playlistVideoRenderer entries
+pub fn parse_playlist_video_ids(initial_data: &serde_json::Value) -> Vec<String> {
+    let mut ids = Vec::new();
+    collect_video_ids(initial_data, &["playlistVideoRenderer"], &mut ids);
+    ids
+}
+
+// Fetch a playlist page and list the IDs of the videos it contains
+// Only the videos included in the first page load are returned (YouTube loads
+// the rest lazily as you scroll), which covers playlists of up to 100 videos
+pub async fn fetch_playlist_video_ids(
+    playlist_id: &str,
+    options: &FetchOptions,
+) -> Result<Vec<String>, TranscriptError> {
+    let client = HttpClient::new(options)?;
+
+    info!("Fetching playlist page...");
+    let response = client.get(&build_playlist_url(options.base_url(), playlist_id)).await?;
+    let html = read_text(response, options.max_page_size).await?;
+
+    let json_str = extract_initial_data(&html).ok_or(TranscriptError::PlayerDataNotFound)?;
+    let initial_data: serde_json::Value = serde_json::from_str(json_str)?;
+
+    let ids = parse_playlist_video_ids(&initial_data);
+    info!("Found {} videos in playlist", ids.len());
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_the_videos_of_a_captured_playlist_page() {
+        let html = include_str!("../tests/fixtures/playlist_page.html");
+        let initial_data: serde_json::Value =
+            serde_json::from_str(extract_initial_data(html).unwrap()).unwrap();
+        assert_eq!(
+            parse_playlist_video_ids(&initial_data),
+            ["aaaaaaaaaa1", "bbbbbbbbbb2", "ccccccccc-3"]
+        );
+    }
+
+    #[test]
+    fn parses_playlist_links_and_ids() {
+        let id = Some("PLabcdefghij".to_string());
+        assert_eq!(parse_playlist_id("https://www.youtube.com/playlist?list=PLabcdefghij"), id);
+        assert_eq!(parse_playlist_id("https://www.youtube.com/watch?v=x&list=PLabcdefghij&t=1"), id);
+        assert_eq!(parse_playlist_id("PLabcdefghij"), id);
+        assert_eq!(parse_playlist_id("short"), None);
+    }
+}
@@ -0,0 +1,108 @@
+// Importers for SubRip and WebVTT files, the reverse of to_srt and to_vtt, so
+// saved or third-party subtitles can be run through the same processing
+use crate::{parse_timestamp, TranscriptError, TranscriptItem};
+
+// Parse one "start --> end" line; WebVTT may put cue settings after the end time
+fn parse_cue_timing(line: &str) -> Result<(f64, f64), TranscriptError> {
+    let invalid = || TranscriptError::Parse(format!("cue timing {:?}", line));
+    let (start, rest) = line.split_once("-->").ok_or_else(invalid)?;
+    let end = rest.split_whitespace().next().ok_or_else(invalid)?;
+    // SRT separates the milliseconds with a comma, WebVTT with a dot
+    let parse = |time: &str| parse_timestamp(&time.trim().replace(',', ".")).ok_or_else(invalid);
+    Ok((parse(start)?, parse(end)?))
+}
+
+// Split subtitle text into cues: blocks separated by blank lines, each with a
+// timing line followed by its text. Anything before the timing line (an SRT cue
+// number, a WebVTT cue identifier) is skipped, and blocks without one (the
+// WEBVTT header, NOTE and STYLE blocks) contribute no entry
+fn parse_cues(content: &str) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    let content = content.replace("\r\n", "\n");
+    let mut transcript = Vec::new();
+
+    for block in content.split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let (start, end) = parse_cue_timing(timing)?;
+        transcript.push(TranscriptItem {
+            text: lines.collect::<Vec<_>>().join("\n"),
+            start,
+            duration: (end - start).max(0.0),
+            index: transcript.len(),
+        });
+    }
+
+    Ok(transcript)
+}
+
+// Read SubRip (SRT) subtitles back into transcript entries
+pub fn parse_srt(content: &str) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    parse_cues(content)
+}
+
+// Read WebVTT subtitles back into transcript entries
+pub fn parse_vtt(content: &str) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    let content = content.trim_start_matches('\u{feff}');
+    if !content.starts_with("WEBVTT") {
+        return Err(TranscriptError::Parse("WebVTT file without a WEBVTT header".to_string()));
+    }
+    parse_cues(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_srt, to_vtt};
+
+    fn sample() -> Vec<TranscriptItem> {
+        [("Hello there", 0.0, 1.5), ("Two\nlines", 1.5, 2.25), ("Late one", 3725.123, 4.0)]
+            .into_iter()
+            .enumerate()
+            .map(|(index, (text, start, duration))| TranscriptItem {
+                text: text.to_string(),
+                start,
+                duration,
+                index,
+            })
+            .collect()
+    }
+
+    fn assert_round_trips(parsed: Vec<TranscriptItem>) {
+        let original = sample();
+        assert_eq!(parsed.len(), original.len());
+        for (parsed, original) in parsed.iter().zip(&original) {
+            assert_eq!(parsed.text, original.text);
+            assert_eq!(parsed.index, original.index);
+            assert!((parsed.start - original.start).abs() < 0.001);
+            assert!((parsed.duration - original.duration).abs() < 0.002);
+        }
+    }
+
+    #[test]
+    fn srt_round_trips() {
+        assert_round_trips(parse_srt(&to_srt(&sample())).unwrap());
+    }
+
+    #[test]
+    fn vtt_round_trips() {
+        assert_round_trips(parse_vtt(&to_vtt(&sample())).unwrap());
+    }
+
+    #[test]
+    fn vtt_skips_notes_and_cue_settings() {
+        let vtt = "\u{feff}WEBVTT\r\n\r\nNOTE made by hand\r\n\r\n\
+                   intro\r\n00:01.000 --> 00:02.500 align:start\r\nHi\r\n";
+        let transcript = parse_vtt(vtt).unwrap();
+        assert_eq!(transcript.len(), 1);
+        let cue = &transcript[0];
+        assert_eq!((cue.text.as_str(), cue.start, cue.duration), ("Hi", 1.0, 1.5));
+    }
+
+    #[test]
+    fn broken_timings_and_headers_are_errors() {
+        assert!(parse_srt("1\n00:00:01,000 --> soon\nHi\n").is_err());
+        assert!(parse_vtt("00:01.000 --> 00:02.000\nHi\n").is_err());
+    }
+}
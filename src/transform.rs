@@ -0,0 +1,430 @@
+use crate::{collapse_whitespace, TranscriptError, TranscriptItem};
+
+// Longest stretch of captions merged into one sentence when no punctuation turns up
+pub const MAX_SENTENCE_SECS: f64 = 20.0;
+
+fn ends_sentence(text: &str) -> bool {
+    text.trim_end()
+        .trim_end_matches(['"', '\'', ')', '\u{201d}', '\u{2019}'])
+        .ends_with(['.', '?', '!'])
+}
+
+// Marker hand-made captions put in front of a new speaker's line, e.g. ">> JOHN: Hi"
+pub const SPEAKER_MARKER: &str = ">>";
+
+// Longest speaker name recognized in a "Name:" label
+const MAX_SPEAKER_NAME_LEN: usize = 30;
+
+// Whether an entry starts a new speaker's turn, i.e. begins with ">>"
+pub fn starts_speaker_turn(text: &str) -> bool {
+    text.trim_start().starts_with(SPEAKER_MARKER)
+}
+
+// The speaker name of a line starting with a "Name:" label, such as "JOHN:",
+// "Dr. Smith:" or "Speaker 2:": at most three words, the first one capitalized,
+// followed by a colon and a space (or nothing else). "10:30" and "Note that: ..."
+// don't count
+pub fn speaker_label(text: &str) -> Option<&str> {
+    let text = text.trim_start().trim_start_matches(SPEAKER_MARKER).trim_start();
+    let (name, rest) = text.split_once(':')?;
+    let words: Vec<&str> = name.split(' ').collect();
+    let is_name = name.len() <= MAX_SPEAKER_NAME_LEN
+        && name.starts_with(char::is_uppercase)
+        && words.len() <= 3
+        && words.iter().all(|word| {
+            word.starts_with(|c: char| c.is_uppercase() || c.is_ascii_digit())
+                && word.chars().all(|c| c.is_alphanumeric() || matches!(c, '.' | '\'' | '-'))
+        });
+    (is_name && (rest.is_empty() || rest.starts_with(' '))).then_some(name)
+}
+
+// Put the ">>" marker in front of every entry that starts with a "Name:" label,
+// so the turn isn't merged into the previous speaker's line when grouping
+pub fn mark_speaker_turns(transcript: &[TranscriptItem]) -> Vec<TranscriptItem> {
+    transcript
+        .iter()
+        .map(|item| {
+            if speaker_label(&item.text).is_some() && !starts_speaker_turn(&item.text) {
+                TranscriptItem {
+                    text: format!("{} {}", SPEAKER_MARKER, item.text.trim_start()),
+                    ..item.clone()
+                }
+            } else {
+                item.clone()
+            }
+        })
+        .collect()
+}
+
+// Join the short fragments auto captions come in into sentence-like entries
+// Fragments are concatenated until one ends with ".", "?" or "!" (or the merged
+// entry has run for MAX_SENTENCE_SECS). Each merged entry keeps the start of its
+// first fragment and the summed duration of all of them. A new speaker's turn
+// (see starts_speaker_turn) always starts a new entry
+pub fn merge_into_sentences(transcript: &[TranscriptItem]) -> Vec<TranscriptItem> {
+    let mut sentences = Vec::new();
+    let mut current: Option<TranscriptItem> = None;
+
+    for item in transcript {
+        let text = collapse_whitespace(&item.text);
+        if text.is_empty() {
+            continue;
+        }
+
+        if starts_speaker_turn(&text) {
+            sentences.extend(current.take());
+        }
+
+        let merged = match current.take() {
+            Some(mut sentence) => {
+                sentence.text.push(' ');
+                sentence.text.push_str(&text);
+                sentence.duration += item.duration;
+                sentence
+            }
+            None => TranscriptItem {
+                text,
+                ..item.clone()
+            },
+        };
+
+        if ends_sentence(&merged.text) || merged.duration >= MAX_SENTENCE_SECS {
+            sentences.push(merged);
+        } else {
+            current = Some(merged);
+        }
+    }
+
+    sentences.extend(current);
+    sentences
+}
+
+// Words marking a bracketed span as a non-speech annotation, e.g. [Music] or (laughs)
+pub const ANNOTATION_KEYWORDS: &[&str] = &[
+    "music", "applause", "laughter", "laughs", "laughing", "cheering", "cheers",
+    "inaudible", "silence", "noise", "sound", "sighs", "coughs", "foreign",
+];
+
+// A span is an annotation only if one of its words is a keyword, so bracketed
+// speech like "(I mean)" is left alone
+fn is_annotation(inner: &str, keywords: &[&str]) -> bool {
+    inner
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .any(|word| keywords.iter().any(|keyword| word.eq_ignore_ascii_case(keyword)))
+}
+
+fn strip_annotation_spans(text: &str, keywords: &[&str]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(open) = rest.find(['[', '(']) {
+        let close_char = if rest[open..].starts_with('[') { ']' } else { ')' };
+        let Some(len) = rest[open + 1..].find(close_char) else {
+            break;
+        };
+        let close = open + 1 + len;
+        result.push_str(&rest[..open]);
+        if !is_annotation(&rest[open + 1..close], keywords) {
+            result.push_str(&rest[open..=close]);
+        }
+        rest = &rest[close + 1..];
+    }
+
+    result.push_str(rest);
+    collapse_whitespace(&result)
+}
+
+// Remove [...] and (...) annotations such as [Music] or [MUSIC PLAYING] from the
+// caption text, dropping entries that consisted of nothing else
+pub fn strip_annotations(transcript: &[TranscriptItem], keywords: &[&str]) -> Vec<TranscriptItem> {
+    transcript
+        .iter()
+        .filter_map(|item| {
+            let text = strip_annotation_spans(&item.text, keywords);
+            (!text.is_empty()).then(|| TranscriptItem {
+                text,
+                ..item.clone()
+            })
+        })
+        .collect()
+}
+
+// The ASCII stand-in for a typographic character, None for characters kept as they are
+fn ascii_equivalent(c: char) -> Option<&'static str> {
+    Some(match c {
+        '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' | '\u{2032}' => "'",
+        '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{201f}' | '\u{2033}' | '\u{00ab}' | '\u{00bb}' => "\"",
+        '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2014}' | '\u{2015}' | '\u{2212}' => "-",
+        '\u{2026}' => "...",
+        '\u{2022}' => "*",
+        // Non-breaking, thin and other fixed-width spaces
+        '\u{00a0}' | '\u{2007}' | '\u{2009}' | '\u{200a}' | '\u{202f}' => " ",
+        // Zero-width characters that only get in the way
+        '\u{200b}' | '\u{feff}' => "",
+        _ => return None,
+    })
+}
+
+// Replace curly quotes, dashes, ellipses and special spaces with their plain
+// ASCII counterparts, so "\u{201c}quote\u{201d} \u{2014} dash" becomes "\"quote\" - dash"
+// Other non-ASCII text, accented letters and the like, is left alone
+pub fn ascii_punctuation(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match ascii_equivalent(c) {
+            Some(replacement) => result.push_str(replacement),
+            None => result.push(c),
+        }
+    }
+    result
+}
+
+// ascii_punctuation applied to the text of every entry
+pub fn normalize_punctuation(transcript: &[TranscriptItem]) -> Vec<TranscriptItem> {
+    transcript
+        .iter()
+        .map(|item| TranscriptItem {
+            text: ascii_punctuation(&item.text),
+            ..item.clone()
+        })
+        .collect()
+}
+
+// Number of words at the end of `previous` that the start of `current` repeats
+fn overlap_len(previous: &[&str], current: &[&str]) -> usize {
+    (1..=previous.len().min(current.len()))
+        .rev()
+        .find(|&len| {
+            previous[previous.len() - len..]
+                .iter()
+                .zip(&current[..len])
+                .all(|(a, b)| a.to_lowercase() == b.to_lowercase())
+        })
+        .unwrap_or(0)
+}
+
+// Remove the words rolling auto captions repeat from the previous line, so
+// "hello there" followed by "there world" becomes "hello there" and "world"
+// An entry that only repeats earlier words is dropped and its time is added to
+// the entry before it
+pub fn dedupe_rolling(transcript: &[TranscriptItem]) -> Vec<TranscriptItem> {
+    let mut deduped: Vec<TranscriptItem> = Vec::new();
+    // The overlap is checked against the previous line as it was captioned, not
+    // what's left of it, since each line rolls over the full text of the one before
+    let mut previous_words: Vec<&str> = Vec::new();
+
+    for item in transcript {
+        let words: Vec<&str> = item.text.split_whitespace().collect();
+        let overlap = overlap_len(&previous_words, &words);
+
+        match deduped.last_mut() {
+            Some(previous) if overlap == words.len() => {
+                previous.duration = previous.duration.max(item.end() - previous.start);
+            }
+            _ if overlap == 0 => deduped.push(item.clone()),
+            _ => deduped.push(TranscriptItem {
+                text: words[overlap..].join(" "),
+                ..item.clone()
+            }),
+        }
+
+        previous_words = words;
+    }
+
+    deduped
+}
+
+// Keep the entries starting inside [start_secs, end_secs): the start is inclusive,
+// the end exclusive, and a missing bound leaves that side open
+pub fn filter_range(
+    transcript: &[TranscriptItem],
+    start_secs: Option<f64>,
+    end_secs: Option<f64>,
+) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    let start = start_secs.unwrap_or(0.0);
+    if let Some(end) = end_secs {
+        if end < start {
+            return Err(TranscriptError::InvalidRange { start, end });
+        }
+    }
+
+    Ok(transcript
+        .iter()
+        .filter(|item| item.start >= start && end_secs.is_none_or(|end| item.start < end))
+        .cloned()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(text: &str, start: f64, duration: f64) -> TranscriptItem {
+        TranscriptItem {
+            text: text.to_string(),
+            start,
+            duration,
+            index: 0,
+        }
+    }
+
+    fn texts(transcript: &[TranscriptItem]) -> Vec<&str> {
+        transcript.iter().map(|item| item.text.as_str()).collect()
+    }
+
+    #[test]
+    fn fragments_merge_into_sentences() {
+        let fragments = [
+            item("so today we", 0.0, 1.5),
+            item("are going to", 1.5, 1.0),
+            item("talk about Rust.", 2.5, 2.0),
+            item("Ready?", 4.5, 1.0),
+            item("  let's   go", 5.5, 1.0),
+        ];
+        let sentences = merge_into_sentences(&fragments);
+        assert_eq!(
+            texts(&sentences),
+            ["so today we are going to talk about Rust.", "Ready?", "let's go"]
+        );
+        assert_eq!((sentences[0].start, sentences[0].duration), (0.0, 4.5));
+        assert_eq!((sentences[2].start, sentences[2].duration), (5.5, 1.0));
+    }
+
+    #[test]
+    fn a_sentence_without_punctuation_stops_at_the_time_limit() {
+        let fragments: Vec<TranscriptItem> =
+            (0..6).map(|i| item("on and on", i as f64 * 5.0, 5.0)).collect();
+        let sentences = merge_into_sentences(&fragments);
+        assert_eq!(sentences.len(), 2);
+        assert_eq!(sentences[0].duration, MAX_SENTENCE_SECS);
+        assert_eq!(sentences[1].start, 20.0);
+    }
+
+    #[test]
+    fn annotations_are_stripped_and_speech_kept() {
+        let transcript = [
+            item("[Music]", 0.0, 2.0),
+            item("[MUSIC PLAYING]", 2.0, 2.0),
+            item("welcome back [Applause] everyone", 4.0, 2.0),
+            item("he said [inaudible] (laughs)", 6.0, 2.0),
+            item("the answer (I mean) is [sic] yes", 8.0, 2.0),
+        ];
+        let stripped = strip_annotations(&transcript, ANNOTATION_KEYWORDS);
+        assert_eq!(
+            texts(&stripped),
+            ["welcome back everyone", "he said", "the answer (I mean) is [sic] yes"]
+        );
+        assert_eq!(stripped[0].start, 4.0);
+    }
+
+    #[test]
+    fn only_the_given_keywords_count_as_annotations() {
+        let transcript = [item("[Music] and [Crowd]", 0.0, 2.0)];
+        assert_eq!(texts(&strip_annotations(&transcript, &["crowd"])), ["[Music] and"]);
+    }
+
+    #[test]
+    fn rolling_captions_lose_the_repeated_words() {
+        let rolling = [
+            item("hello there", 0.0, 2.0),
+            item("there world", 1.5, 2.0),
+            item("World", 3.0, 1.5),
+            item("world how are you", 4.0, 2.0),
+        ];
+        let deduped = dedupe_rolling(&rolling);
+        assert_eq!(texts(&deduped), ["hello there", "world", "how are you"]);
+        assert_eq!(texts(&deduped).join(" "), "hello there world how are you");
+        // The entry repeating "World" only extends the one before it
+        assert_eq!((deduped[1].start, deduped[1].duration), (1.5, 3.0));
+    }
+
+    #[test]
+    fn lines_without_overlap_are_left_alone() {
+        let transcript = [item("one two", 0.0, 1.0), item("three four", 1.0, 1.0)];
+        assert_eq!(dedupe_rolling(&transcript), transcript);
+    }
+
+    #[test]
+    fn the_range_includes_its_start_and_excludes_its_end() {
+        let transcript: Vec<TranscriptItem> =
+            [0.0, 9.9, 10.0, 19.9, 20.0].iter().map(|&start| item("x", start, 1.0)).collect();
+        let starts = |kept: Vec<TranscriptItem>| -> Vec<f64> {
+            kept.iter().map(|item| item.start).collect()
+        };
+        assert_eq!(starts(filter_range(&transcript, Some(10.0), Some(20.0)).unwrap()), [10.0, 19.9]);
+        assert_eq!(starts(filter_range(&transcript, Some(19.9), None).unwrap()), [19.9, 20.0]);
+        assert_eq!(starts(filter_range(&transcript, None, Some(9.9)).unwrap()), [0.0]);
+        assert!(filter_range(&transcript, Some(5.0), Some(5.0)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_range_ending_before_it_starts_is_an_error() {
+        assert!(matches!(
+            filter_range(&[], Some(30.0), Some(10.0)),
+            Err(TranscriptError::InvalidRange { start, end }) if start == 30.0 && end == 10.0
+        ));
+    }
+
+    #[test]
+    fn two_speakers_stay_on_separate_lines() {
+        let transcript = [
+            item(">> ALICE: So where were", 0.0, 1.0),
+            item("you last night", 1.0, 1.0),
+            item(">> BOB: At home", 2.0, 1.0),
+            item("all evening.", 3.0, 1.0),
+        ];
+        // Sentence merging would otherwise run both turns together
+        assert_eq!(
+            texts(&merge_into_sentences(&transcript)),
+            [">> ALICE: So where were you last night", ">> BOB: At home all evening."]
+        );
+        // and so would grouping into one bucket
+        let normalized = crate::normalize_items(&transcript, 30);
+        assert_eq!(
+            texts(&normalized),
+            [">> ALICE: So where were you last night", ">> BOB: At home all evening."]
+        );
+        assert!(normalized.iter().all(|item| item.start == 0.0));
+    }
+
+    #[test]
+    fn name_labels_are_marked_as_turns() {
+        let transcript = [item("ALICE: Hi", 0.0, 1.0), item("Dr. Smith: Hello", 1.0, 1.0), item("at 10:30 sharp", 2.0, 1.0)];
+        assert_eq!(
+            texts(&mark_speaker_turns(&transcript)),
+            [">> ALICE: Hi", ">> Dr. Smith: Hello", "at 10:30 sharp"]
+        );
+        assert_eq!(speaker_label(">> Speaker 2: yes"), Some("Speaker 2"));
+        assert_eq!(speaker_label("Note that: it rains"), None);
+    }
+
+    #[test]
+    fn indices_are_contiguous_and_survive_filtering() {
+        let xml = r#"<transcript>
+            <text start="0" dur="1">zero</text>
+            <text start="2" dur="1">one</text>
+            <text start="4" dur="1">[Music]</text>
+            <text start="6" dur="1">three</text>
+            <text start="8" dur="1">four</text>
+        </transcript>"#;
+        let transcript = crate::parse_transcript_xml(xml).unwrap();
+        let indices: Vec<usize> = transcript.iter().map(|item| item.index).collect();
+        assert_eq!(indices, [0, 1, 2, 3, 4]);
+
+        let ranged = filter_range(&transcript, Some(2.0), Some(8.0)).unwrap();
+        let stripped = strip_annotations(&ranged, ANNOTATION_KEYWORDS);
+        let kept: Vec<(usize, &str)> = stripped.iter().map(|item| (item.index, item.text.as_str())).collect();
+        assert_eq!(kept, [(1, "one"), (3, "three")]);
+
+        let json: serde_json::Value = serde_json::from_str(&crate::to_json(&stripped).unwrap()).unwrap();
+        assert_eq!(json[1]["index"], 3);
+    }
+
+    #[test]
+    fn typographic_punctuation_becomes_ascii() {
+        assert_eq!(ascii_punctuation("\u{201c}quote\u{201d} \u{2014} dash"), "\"quote\" - dash");
+        assert_eq!(ascii_punctuation("it\u{2019}s\u{00a0}fine\u{2026}"), "it's fine...");
+        assert_eq!(ascii_punctuation("caf\u{e9} na\u{ef}ve"), "caf\u{e9} na\u{ef}ve");
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{TranscriptError, TranscriptItem};
+use log::{debug, warn};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+// How many <text> segments a caption document held, and how many of them made
+// it into the transcript
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentCounts {
+    pub seen: usize,
+    pub parsed: usize,
+}
+
+// Timing pulled from the attributes of a <text> element, looked up by name
+// so the order YouTube happens to emit them in doesn't matter
+struct CueAttributes {
+    start: Option<String>,
+    dur: Option<String>,
+}
+
+// A malformed attribute (say, a missing quote) only costs the attributes after
+// it, so one broken cue is skipped like a cue without a start instead of
+// failing the whole document
+fn cue_attributes(element: &BytesStart) -> CueAttributes {
+    let mut attrs = CueAttributes { start: None, dur: None };
+    for attr in element.attributes() {
+        let attr = match attr {
+            Ok(attr) => attr,
+            Err(e) => {
+                debug!("Malformed caption attribute: {}", e);
+                break;
+            }
+        };
+        let value = attr.value.into_owned();
+        match attr.key.as_ref() {
+            "start" => attrs.start = Some(value),
+            "dur" => attrs.dur = Some(value),
+            _ => {}
+        }
+    }
+    attrs
+}
+
+// Duration given to a final cue that has no dur attribute and no following cue
+// to measure against
+const LAST_CUE_DURATION: f64 = 2.0;
+
+// Caption text is sometimes encoded twice ("it&amp;#39;s"), so entities are
+// decoded until nothing changes, giving up after this many passes
+const MAX_DECODE_PASSES: usize = 4;
+
+// Decode HTML entities, repeating for text that was encoded more than once
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = text.to_string();
+    for _ in 0..MAX_DECODE_PASSES {
+        let next = html_escape::decode_html_entities(&decoded);
+        if next == decoded {
+            break;
+        }
+        decoded = next.into_owned();
+    }
+    decoded
+}
+
+// Seconds from a start or dur attribute, None when it isn't a usable number
+fn parse_seconds(value: &str) -> Option<f64> {
+    value.trim().parse().ok().filter(|secs: &f64| secs.is_finite() && *secs >= 0.0)
+}
+
+// Parse YouTube's timedtext XML into transcript items
+// Every <text start=".."> element becomes one item. Character content is
+// collected from the element and anything nested inside it (styled <font>
+// spans and the like), with <br> turned into a space, and entities are decoded
+// once the whole cue has been read (repeatedly, see decode_entities)
+// Auto-generated tracks sometimes leave out dur, in which case the cue is
+// assumed to last until the next one starts
+// Segments without a usable start time (missing, malformed or not a number) are
+// skipped rather than failing the whole track, with a warning saying how many
+// of them were lost; only a track where nothing parses ends up empty
+// Attributes are read by the XML reader, which respects quoting, so a styled
+// cue like <text start="1" style="a>b"> keeps its text intact
+pub fn parse_transcript_xml(xml: &str) -> Result<Vec<TranscriptItem>, TranscriptError> {
+    let (transcript, counts) = parse_transcript_xml_with_counts(xml)?;
+    if counts.parsed < counts.seen {
+        warn!("Parsed {} of {} caption segments", counts.parsed, counts.seen);
+    }
+    Ok(transcript)
+}
+
+// Same as parse_transcript_xml, also reporting how many segments were seen
+pub fn parse_transcript_xml_with_counts(
+    xml: &str,
+) -> Result<(Vec<TranscriptItem>, SegmentCounts), TranscriptError> {
+    let mut reader = Reader::from_str(xml);
+    let mut cues: Vec<(f64, Option<f64>, String)> = Vec::new();
+    let mut seen = 0;
+
+    // The attributes and raw text of the <text> element we're currently inside
+    let mut current: Option<(CueAttributes, String)> = None;
+    // How many elements deep we are inside that <text> element
+    let mut depth = 0;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| TranscriptError::Parse(format!("caption XML: {}", e)))?;
+
+        match event {
+            Event::Start(e) if current.is_none() && e.name().as_ref() == "text" => {
+                seen += 1;
+                current = Some((cue_attributes(&e), String::new()));
+                depth = 0;
+            }
+            Event::Empty(e) if current.is_none() && e.name().as_ref() == "text" => {
+                // A self-closing <text/> carries no words, nothing to keep
+            }
+            Event::Start(_) if current.is_some() => depth += 1,
+            Event::Empty(e) => {
+                if let Some((_, text)) = current.as_mut() {
+                    if e.name().as_ref() == "br" {
+                        text.push(' ');
+                    }
+                }
+            }
+            Event::Text(t) => {
+                if let Some((_, text)) = current.as_mut() {
+                    text.push_str(&t.xml10_content());
+                }
+            }
+            Event::CData(t) => {
+                if let Some((_, text)) = current.as_mut() {
+                    text.push_str(&t);
+                }
+            }
+            Event::GeneralRef(r) => {
+                // Keep entity references as written, they're decoded below
+                if let Some((_, text)) = current.as_mut() {
+                    text.push('&');
+                    text.push_str(&r);
+                    text.push(';');
+                }
+            }
+            Event::End(_) if current.is_some() && depth > 0 => depth -= 1,
+            Event::End(_) => {
+                if let Some((attrs, raw)) = current.take() {
+                    // A malformed dur is treated like a missing one
+                    if let Some(start) = attrs.start.as_deref().and_then(parse_seconds) {
+                        cues.push((
+                            start,
+                            attrs.dur.as_deref().and_then(parse_seconds),
+                            decode_entities(&raw),
+                        ));
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let mut transcript = Vec::with_capacity(cues.len());
+    for (i, (start, duration, text)) in cues.iter().enumerate() {
+        let duration = match (duration, cues.get(i + 1)) {
+            (Some(duration), _) => *duration,
+            (None, Some((next, _, _))) if next > start => next - start,
+            (None, _) => LAST_CUE_DURATION,
+        };
+        transcript.push(TranscriptItem {
+            text: text.clone(),
+            start: *start,
+            duration,
+            index: i,
+        });
+    }
+
+    let counts = SegmentCounts {
+        seen,
+        parsed: transcript.len(),
+    };
+    Ok((transcript, counts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(transcript: &[TranscriptItem]) -> Vec<&str> {
+        transcript.iter().map(|item| item.text.as_str()).collect()
+    }
+
+    #[test]
+    fn reads_attributes_in_any_order_and_nested_markup() {
+        let xml = include_str!("../tests/fixtures/captions_markup.xml");
+        let transcript = parse_transcript_xml(xml).unwrap();
+        assert_eq!(
+            texts(&transcript),
+            ["Plain line", "First half second half", "styled and more nested", "fish & chips"]
+        );
+        assert_eq!((transcript[0].start, transcript[0].duration), (0.5, 2.5));
+        assert_eq!((transcript[2].start, transcript[2].duration), (4.5, 2.0));
+    }
+
+    #[test]
+    fn infers_missing_durations_from_the_next_cue() {
+        let xml = include_str!("../tests/fixtures/captions_asr_no_dur.xml");
+        let transcript = parse_transcript_xml(xml).unwrap();
+        assert_eq!(transcript.len(), 4);
+        assert!((transcript[0].duration - 2.24).abs() < 1e-9);
+        assert!((transcript[1].duration - 1.52).abs() < 1e-9);
+        // A dur that is present wins
+        assert_eq!(transcript[2].duration, 1.2);
+        // The last cue has nothing to measure against
+        assert_eq!(transcript[3].duration, LAST_CUE_DURATION);
+    }
+
+    #[test]
+    fn counts_the_segments_that_could_not_be_parsed() {
+        let xml = include_str!("../tests/fixtures/captions_malformed.xml");
+        let (transcript, counts) = parse_transcript_xml_with_counts(xml).unwrap();
+        assert_eq!(texts(&transcript), ["first", "second", "third"]);
+        assert_eq!(counts, SegmentCounts { seen: 6, parsed: 3 });
+    }
+
+    #[test]
+    fn a_clean_document_parses_every_segment() {
+        let xml = include_str!("../tests/fixtures/captions_markup.xml");
+        let (transcript, counts) = parse_transcript_xml_with_counts(xml).unwrap();
+        assert_eq!(counts.seen, counts.parsed);
+        assert_eq!(counts.parsed, transcript.len());
+    }
+
+    #[test]
+    fn double_encoded_entities_come_out_clean() {
+        assert_eq!(decode_entities("it&amp;#39;s"), "it's");
+        assert_eq!(decode_entities("fish &amp;amp; chips &quot;to go&quot;"), "fish & chips \"to go\"");
+        assert_eq!(decode_entities("&#x2019;&eacute;"), "\u{2019}\u{e9}");
+        assert_eq!(decode_entities("plain text"), "plain text");
+    }
+
+    #[test]
+    fn decoding_stops_after_a_few_passes() {
+        // Five layers of encoding, one more than is ever undone
+        let layered = "&amp;amp;amp;amp;lt;";
+        assert_eq!(decode_entities(layered), "&lt;");
+    }
+
+    #[test]
+    fn doubly_encoded_cue_text_is_decoded() {
+        let xml = r#"<transcript><text start="1" dur="1">it&amp;#39;s &amp;amp; more</text></transcript>"#;
+        assert_eq!(texts(&parse_transcript_xml(xml).unwrap()), ["it's & more"]);
+    }
+
+    #[test]
+    fn a_bracket_inside_an_attribute_value_stays_out_of_the_text() {
+        let transcript = parse_transcript_xml(include_str!("../tests/fixtures/captions_styled.xml")).unwrap();
+        let cues: Vec<(&str, f64, f64)> =
+            transcript.iter().map(|item| (item.text.as_str(), item.start, item.duration)).collect();
+        assert_eq!(cues, [("hi", 1.0, 2.0), ("still > here", 3.5, 1.25)]);
+    }
+}